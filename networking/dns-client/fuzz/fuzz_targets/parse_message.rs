@@ -0,0 +1,27 @@
+#![no_main]
+
+use dns_client::message::Message;
+use dns_client::output::{Json, Verbose};
+use libfuzzer_sys::fuzz_target;
+use std::time::Duration;
+
+// Parsing arbitrary bytes must either fail or give a message that can be displayed and encoded
+// like any other. Run with: cargo fuzz run parse_message
+fuzz_target!(|data: &[u8]| {
+    let Ok(message) = Message::try_from(data) else {
+        return;
+    };
+    let server = ([127, 0, 0, 1], 53).into();
+    let verbose = Verbose {
+        response: &message,
+        server,
+        elapsed: Duration::ZERO,
+    };
+    let _ = verbose.to_string();
+    let _ = serde_json::to_string(&Json::new(&message, server, Duration::ZERO));
+    let _ = message.check_rcode();
+    // Whatever we parsed must survive being encoded and parsed again.
+    if let Ok(encoded) = message.encode() {
+        Message::try_from(encoded.as_slice()).expect("re-encoded message does not parse");
+    }
+});
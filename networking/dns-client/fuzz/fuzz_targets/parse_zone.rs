@@ -0,0 +1,13 @@
+#![no_main]
+
+use dns_client::name::Name;
+use dns_client::zone::Zone;
+use libfuzzer_sys::fuzz_target;
+
+// Zone files come from users, so any text must either load or be rejected with an error. Run
+// with: cargo fuzz run parse_zone
+fuzz_target!(|text: &str| {
+    if let Ok(zone) = Zone::parse(text, Name::root()) {
+        let _ = zone.soa().to_string();
+    }
+});
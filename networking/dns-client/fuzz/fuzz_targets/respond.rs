@@ -0,0 +1,32 @@
+#![no_main]
+
+use dns_client::message::Message;
+use dns_client::name::Name;
+use dns_client::server::{self, Server};
+use dns_client::zone::Zone;
+use libfuzzer_sys::fuzz_target;
+use std::sync::OnceLock;
+
+const ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@       SOA   ns1 hostmaster 1 7200 3600 1209600 300
+        NS    ns1
+ns1     A     192.0.2.1
+www     A     192.0.2.80
+alias   CNAME www
+sub     NS    ns.sub
+ns.sub  A     192.0.2.53
+";
+
+// The server parses whatever arrives on its sockets, so any request must get a well-formed
+// response or none at all. Run with: cargo fuzz run respond
+fuzz_target!(|request: &[u8]| {
+    static SERVER: OnceLock<Server> = OnceLock::new();
+    let server = SERVER.get_or_init(|| Server::new(Zone::parse(ZONE, Name::root()).unwrap()));
+    for udp in [true, false] {
+        if let Some(response) = server::respond(server, request, udp) {
+            Message::try_from(response.as_slice()).expect("response does not parse");
+        }
+    }
+});
@@ -0,0 +1,363 @@
+use crate::edns::Edns;
+use crate::message::{Message, Record};
+use crate::query::Query;
+use crate::rdata::RecordType;
+use crate::resolver::Response;
+use crate::transport::{backoff, Options};
+use anyhow::{anyhow, bail, Context, Error};
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+// Largest possible UDP payload, so responses are never cut off by our receive buffer.
+const MAX_DGRAM_SIZE: usize = u16::MAX as usize;
+
+// Most queries that may wait for a response at once. Staying well below the 65536 possible IDs
+// keeps finding an unused one quick, and makes guessing one harder for spoofed responses.
+const MAX_IN_FLIGHT: usize = 4096;
+
+// How long the receiving task waits after a socket error that doesn't stop it.
+const RECEIVE_ERROR_DELAY: Duration = Duration::from_millis(10);
+
+// Queries waiting for a response, keyed by transaction ID.
+type Pending = Arc<Mutex<HashMap<u16, Waiter>>>;
+
+// An in-flight query: the question to validate responses against, and where to deliver the
+// response.
+struct Waiter {
+    query: Query<String>,
+    servers: Vec<SocketAddr>,
+    sender: oneshot::Sender<(Message, SocketAddr)>,
+}
+
+// An asynchronous stub resolver for use inside a tokio runtime. Every query shares one UDP
+// socket: a background task reads responses from it and hands each one to the query with the
+// matching transaction ID. Dropping a lookup future cancels the query and frees its ID.
+pub struct AsyncResolver {
+    // Nameservers to query, in order of preference. They must all be of the same address family
+    // as the first one, since they share a socket.
+    servers: Vec<SocketAddr>,
+    pub options: Options,
+    pub edns: Option<Edns>,
+    socket: Arc<UdpSocket>,
+    pending: Pending,
+    receiver: JoinHandle<()>,
+}
+
+impl AsyncResolver {
+    // Bind the shared socket and start the task that receives responses. This must be called
+    // from within a tokio runtime.
+    pub async fn new(servers: Vec<SocketAddr>) -> Result<Self, Error> {
+        let first = servers.first().ok_or(anyhow!("no nameservers given"))?;
+        if servers.iter().any(|s| s.is_ipv4() != first.is_ipv4()) {
+            bail!("nameservers must all be IPv4 or all be IPv6");
+        }
+        let local: SocketAddr = match first {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = Arc::new(UdpSocket::bind(local).await?);
+        let pending = Pending::default();
+        let receiver = tokio::spawn(receive(socket.clone(), pending.clone()));
+        Ok(AsyncResolver {
+            servers,
+            options: Options::default(),
+            edns: Some(Edns::default()),
+            socket,
+            pending,
+            receiver,
+        })
+    }
+
+    // Look up the records of the given type for a name, like Resolver::lookup. Since many lookups
+    // may run at once, every error names the lookup that failed.
+    pub async fn lookup(&self, name: &str, r#type: RecordType) -> Result<Vec<Record>, Error> {
+        let context = || format!("{type} lookup for {name} failed");
+        let response = self.send(name, r#type).await.with_context(context)?;
+        response.message.check_rcode().with_context(context)?;
+        Ok(response.message.answers)
+    }
+
+    // Send a query and return the full response, whatever its RCODE. Each attempt waits for the
+    // configured timeout before the query is resent to the next nameserver, and each retry round
+    // starts after the same backoff as the blocking transport. Truncated responses and queries
+    // with TCP forced go over TCP instead. TLS and HTTPS aren't supported.
+    pub async fn send(&self, name: &str, r#type: RecordType) -> Result<Response, Error> {
+        let start = Instant::now();
+        let query = Query {
+            edns: self.edns.clone(),
+            ..Query::new(name.to_string(), r#type)
+        };
+        if self.options.tls.is_some() || self.options.doh.is_some() {
+            bail!("DNS-over-TLS and DNS-over-HTTPS are not supported by the async resolver");
+        }
+        if self.options.tcp {
+            return self.send_tcp(&query, start).await;
+        }
+
+        // Register the query under an unused ID. The guard unregisters it however this function
+        // returns, including when the future is dropped.
+        let (sender, mut receiver) = oneshot::channel();
+        let mut request = Vec::new();
+        let id = {
+            let mut pending = self.pending.lock().unwrap();
+            if self.receiver.is_finished() {
+                bail!("resolver stopped receiving responses");
+            }
+            if pending.len() >= MAX_IN_FLIGHT {
+                bail!("too many queries in flight");
+            }
+            let mut query = query;
+            while pending.contains_key(&query.id) {
+                query.id = rand::random();
+            }
+            query.encode(&mut request)?;
+            let id = query.id;
+            let waiter = Waiter {
+                query,
+                servers: self.servers.clone(),
+                sender,
+            };
+            pending.insert(id, waiter);
+            id
+        };
+        let _guard = Unregister {
+            pending: &self.pending,
+            id,
+        };
+
+        for round in 0..=self.options.retries {
+            time::sleep(backoff(round)).await;
+            for &server in &self.servers {
+                self.socket.send_to(&request, server).await?;
+                match time::timeout(self.options.timeout, &mut receiver).await {
+                    Ok(Ok((message, server))) if message.header.flags.tc => {
+                        let query = Query {
+                            id,
+                            edns: self.edns.clone(),
+                            ..Query::new(name.to_string(), r#type)
+                        };
+                        return tcp(&query, server, &self.options)
+                            .await
+                            .map(|message| Response {
+                                message,
+                                server,
+                                elapsed: start.elapsed(),
+                            });
+                    }
+                    Ok(Ok((message, server))) => {
+                        return Ok(Response {
+                            message,
+                            server,
+                            elapsed: start.elapsed(),
+                        })
+                    }
+                    Ok(Err(_)) => bail!("resolver stopped receiving responses"),
+                    Err(_) => continue,
+                }
+            }
+        }
+        let attempts = (self.options.retries as usize + 1) * self.servers.len();
+        bail!("no response after {attempts} attempts")
+    }
+
+    async fn send_tcp(&self, query: &Query<String>, start: Instant) -> Result<Response, Error> {
+        let mut errors = Vec::new();
+        for round in 0..=self.options.retries {
+            time::sleep(backoff(round)).await;
+            for &server in &self.servers {
+                match tcp(query, server, &self.options).await {
+                    Ok(message) => {
+                        return Ok(Response {
+                            message,
+                            server,
+                            elapsed: start.elapsed(),
+                        })
+                    }
+                    Err(err) => errors.push(format!("{server}: {err}")),
+                }
+            }
+        }
+        Err(anyhow!(
+            "no response after {} attempts:\n  {}",
+            errors.len(),
+            errors.join("\n  ")
+        ))
+    }
+}
+
+impl Drop for AsyncResolver {
+    fn drop(&mut self) {
+        self.receiver.abort();
+    }
+}
+
+// Removes a query from the pending map when dropped.
+struct Unregister<'a> {
+    pending: &'a Pending,
+    id: u16,
+}
+
+impl Drop for Unregister<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+// Read responses from the shared socket, delivering each one to the query it answers. Responses
+// that are malformed, come from an unexpected address, or don't match any pending query are
+// dropped. If the socket fails for good, every pending query fails with it, and so do any sent
+// afterwards.
+async fn receive(socket: Arc<UdpSocket>, pending: Pending) {
+    let mut buf = vec![0; MAX_DGRAM_SIZE];
+    loop {
+        let (n, from) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            // These report an ICMP error for an earlier datagram, or say nothing about the socket.
+            // Pausing keeps a stream of them from spinning.
+            Err(err) if transient(&err) => {
+                time::sleep(RECEIVE_ERROR_DELAY).await;
+                continue;
+            }
+            Err(_) => break,
+        };
+        let Ok(response) = Message::try_from(&buf[..n]) else {
+            continue;
+        };
+        let mut pending = pending.lock().unwrap();
+        let Some(waiter) = pending.get(&response.header.id) else {
+            continue;
+        };
+        if !waiter.servers.contains(&from) || waiter.query.validate(&response).is_err() {
+            continue;
+        }
+        if let Some(waiter) = pending.remove(&response.header.id) {
+            let _ = waiter.sender.send((response, from));
+        }
+    }
+    // Dropping the senders wakes up every waiting query.
+    pending.lock().unwrap().clear();
+}
+
+fn transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+    )
+}
+
+// Perform a single TCP exchange with the same length-prefixed framing as the blocking transport.
+async fn tcp(
+    query: &Query<String>,
+    server: SocketAddr,
+    options: &Options,
+) -> Result<Message, Error> {
+    let exchange = async {
+        let mut request = Vec::new();
+        query.encode(&mut request)?;
+        let mut stream = TcpStream::connect(server).await?;
+        let mut framed = u16::try_from(request.len())?.to_be_bytes().to_vec();
+        framed.extend(request);
+        stream.write_all(&framed).await?;
+
+        let length = stream.read_u16().await?;
+        let mut buf = vec![0; length as usize];
+        stream.read_exact(&mut buf).await?;
+        let response = Message::try_from(buf.as_slice())?;
+        query.validate(&response)?;
+        Ok(response)
+    };
+    time::timeout(options.timeout, exchange)
+        .await
+        .map_err(|_| anyhow!("timed out after {:?}", options.timeout))?
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rdata::RData;
+    use crate::test_support::reply;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn multiplex_queries() {
+        let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolver = AsyncResolver::new(vec![server.local_addr().unwrap()])
+            .await
+            .unwrap();
+        // Receive both queries, then answer them in reverse order.
+        let responder = std::thread::spawn(move || {
+            let mut buf = [0; 512];
+            let mut queries = Vec::new();
+            for _ in 0..2 {
+                let (n, addr) = server.recv_from(&mut buf).unwrap();
+                queries.push((buf[..n].to_vec(), addr));
+            }
+            for (query, addr) in queries.into_iter().rev() {
+                let last = if query[13..].starts_with(b"one") {
+                    1
+                } else {
+                    2
+                };
+                let answers = [RData::A([192, 0, 2, last].into())];
+                server.send_to(&reply(&query, 0, &answers), addr).unwrap();
+            }
+        });
+        let (one, two) = tokio::join!(
+            resolver.lookup("one.example", RecordType::A),
+            resolver.lookup("two.example", RecordType::A),
+        );
+        assert_eq!(one.unwrap()[0].rdata.to_string(), "192.0.2.1");
+        assert_eq!(two.unwrap()[0].rdata.to_string(), "192.0.2.2");
+        assert!(resolver.pending.lock().unwrap().is_empty());
+        responder.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancel_query() {
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut resolver = AsyncResolver::new(vec![silent.local_addr().unwrap()])
+            .await
+            .unwrap();
+        resolver.options.timeout = Duration::from_secs(10);
+
+        let lookup = resolver.lookup("example.com", RecordType::A);
+        tokio::select! {
+            _ = lookup => panic!("the server never responds"),
+            _ = time::sleep(Duration::from_millis(50)) => {}
+        }
+        // The lookup future was dropped, so its ID is free again.
+        assert!(resolver.pending.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn limit_queries_in_flight() {
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolver = AsyncResolver::new(vec![silent.local_addr().unwrap()])
+            .await
+            .unwrap();
+        let mut receivers = Vec::new();
+        for id in 0..MAX_IN_FLIGHT as u16 {
+            let (sender, receiver) = oneshot::channel();
+            let waiter = Waiter {
+                query: Query::new("example.com".to_string(), RecordType::A),
+                servers: Vec::new(),
+                sender,
+            };
+            resolver.pending.lock().unwrap().insert(id, waiter);
+            receivers.push(receiver);
+        }
+        let err = resolver.lookup("example.com", RecordType::A).await;
+        assert!(format!("{:#}", err.unwrap_err()).contains("too many queries in flight"));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::async_resolver::AsyncResolver;
+use crate::message::Record;
+use crate::rdata::RecordType;
+use anyhow::Error;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+// The order in which batch results are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    // The order the names were given in. A slow lookup holds back the results after it.
+    Input,
+    // The order the lookups finish in.
+    Arrival,
+}
+
+// Look up the records of the given type for every name, with at most `limit` lookups in flight
+// at a time. The result of each lookup is passed to on_result along with its name.
+pub async fn lookup_all(
+    resolver: Arc<AsyncResolver>,
+    names: Vec<String>,
+    r#type: RecordType,
+    limit: usize,
+    order: Order,
+    mut on_result: impl FnMut(&str, Result<Vec<Record>, Error>),
+) {
+    let permits = Arc::new(Semaphore::new(limit.max(1)));
+    let mut lookups = JoinSet::new();
+    for (index, name) in names.into_iter().enumerate() {
+        let resolver = resolver.clone();
+        let permits = permits.clone();
+        lookups.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let result = resolver.lookup(&name, r#type).await;
+            (index, name, result)
+        });
+    }
+
+    // Results that finished before one of the names ahead of them, when reporting in input order.
+    let mut held = BTreeMap::new();
+    let mut next = 0;
+    while let Some(joined) = lookups.join_next().await {
+        let (index, name, result) = joined.expect("lookup task panicked");
+        if order == Order::Arrival {
+            on_result(&name, result);
+            continue;
+        }
+        held.insert(index, (name, result));
+        while let Some((name, result)) = held.remove(&next) {
+            on_result(&name, result);
+            next += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rdata::RData;
+    use crate::test_support::reply;
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::Duration;
+
+    // Receive a query for each name, then answer them in reverse order of name with an A record
+    // whose last byte is the first character of the name. The answers are spaced out so they
+    // arrive in a predictable order.
+    fn respond_in_reverse(server: UdpSocket, count: usize) {
+        let mut buf = [0; 512];
+        let mut queries = Vec::new();
+        for _ in 0..count {
+            let (n, addr) = server.recv_from(&mut buf).unwrap();
+            queries.push((buf[..n].to_vec(), addr));
+        }
+        queries.sort_by_key(|(query, _)| std::cmp::Reverse(query[13]));
+        for (query, addr) in queries {
+            let answers = [RData::A([192, 0, 2, query[13] - b'0'].into())];
+            server.send_to(&reply(&query, 0, &answers), addr).unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    async fn lookup_in(order: Order) -> Vec<String> {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolver = AsyncResolver::new(vec![server.local_addr().unwrap()])
+            .await
+            .unwrap();
+        let handle = thread::spawn(move || respond_in_reverse(server, 3));
+        let names = ["1.example", "2.example", "3.example"].map(String::from);
+        let mut results = Vec::new();
+        lookup_all(
+            Arc::new(resolver),
+            names.to_vec(),
+            RecordType::A,
+            3,
+            order,
+            |name, result| results.push(format!("{name} {}", result.unwrap()[0].rdata)),
+        )
+        .await;
+        handle.join().unwrap();
+        results
+    }
+
+    #[tokio::test]
+    async fn report_in_input_order() {
+        assert_eq!(
+            lookup_in(Order::Input).await,
+            [
+                "1.example 192.0.2.1",
+                "2.example 192.0.2.2",
+                "3.example 192.0.2.3"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn report_in_arrival_order() {
+        assert_eq!(
+            lookup_in(Order::Arrival).await,
+            [
+                "3.example 192.0.2.3",
+                "2.example 192.0.2.2",
+                "1.example 192.0.2.1"
+            ]
+        );
+    }
+}
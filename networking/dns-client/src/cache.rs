@@ -0,0 +1,340 @@
+use crate::error::ResponseError;
+use crate::message::{Message, Record};
+use crate::name::Name;
+use crate::rdata::{RData, RecordType};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Cached results are looked up by the question that produced them.
+type Key = (Name, RecordType, u16);
+
+// How many results a cache holds by default.
+const DEFAULT_CAPACITY: usize = 10_000;
+
+// An in-memory cache of lookup results that serves each one until its TTL runs out. Answers are
+// kept for the smallest TTL of their records. Negative results (NXDOMAIN, or a name without
+// records of the type) are kept as described in RFC 2308 section 5: for the TTL of the SOA record
+// in the authority section, capped by its MINIMUM field. Negative responses without an SOA
+// record aren't cached at all, and the authority section is kept with the ones that are, so the
+// SOA can be passed on with the negative result.
+//
+// The cache holds a limited number of results. When it is full, expired results are dropped to
+// make room, and if none have expired, the one closest to expiring is.
+pub struct Cache {
+    state: Mutex<State>,
+    capacity: usize,
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<Key, Entry>,
+    stats: Stats,
+}
+
+struct Entry {
+    result: Result<Vec<Record>, ResponseError>,
+    // The authority section of a negative response. Empty for answers.
+    authority: Vec<Record>,
+    // Whether the response had the AD bit set.
+    authenticated: bool,
+    inserted: Instant,
+    ttl: Duration,
+}
+
+// A result found in the cache, with what else the response it came from said about it.
+#[derive(Debug)]
+pub struct Cached {
+    pub result: Result<Vec<Record>, ResponseError>,
+    // The authority section of a negative result, which holds the SOA record that has to go with
+    // it (RFC 2308 section 3).
+    pub authority: Vec<Record>,
+    // Whether the response had the AD bit set, meaning its resolver validated it with DNSSEC.
+    pub authenticated: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Cache {
+    pub fn new(capacity: usize) -> Self {
+        Cache {
+            state: Mutex::default(),
+            capacity,
+        }
+    }
+
+    // Return the cached result for a question, or None if it isn't cached or has expired. The TTLs
+    // of returned records are reduced by the time they have spent in the cache.
+    pub fn get(
+        &self,
+        name: &Name,
+        r#type: RecordType,
+        class: u16,
+    ) -> Option<Result<Vec<Record>, ResponseError>> {
+        self.lookup(name, r#type, class).map(|cached| cached.result)
+    }
+
+    // Like get, but also return the rest of what is kept with the result.
+    pub fn lookup(&self, name: &Name, r#type: RecordType, class: u16) -> Option<Cached> {
+        let mut state = self.state.lock().unwrap();
+        let key = (name.clone(), r#type, class);
+        let age = match state.entries.get(&key) {
+            Some(entry) if !entry.expired() => entry.inserted.elapsed(),
+            Some(_) => {
+                state.entries.remove(&key);
+                state.stats.misses += 1;
+                return None;
+            }
+            None => {
+                state.stats.misses += 1;
+                return None;
+            }
+        };
+        state.stats.hits += 1;
+        let entry = &state.entries[&key];
+        let age = age.as_secs() as u32;
+        let aged = |records: &[Record]| {
+            records
+                .iter()
+                .map(|record| Record {
+                    ttl: record.ttl.saturating_sub(age),
+                    ..record.clone()
+                })
+                .collect()
+        };
+        let result = entry.result.as_ref().map(|records| aged(records));
+        Some(Cached {
+            result: result.map_err(Clone::clone),
+            authority: aged(&entry.authority),
+            authenticated: entry.authenticated,
+        })
+    }
+
+    // Store the result of a response under its question. Responses with RCODEs other than
+    // NOERROR and NXDOMAIN, and results with a TTL of zero, are not stored.
+    pub fn insert(&self, response: &Message) {
+        let [question] = response.questions.as_slice() else {
+            return;
+        };
+        let (result, ttl) = match response.check_rcode() {
+            Ok(()) if !response.answers.is_empty() => {
+                let ttl = response.answers.iter().map(|r| r.ttl).min();
+                (Ok(response.answers.clone()), ttl)
+            }
+            Ok(()) => (Ok(Vec::new()), negative_ttl(response)),
+            Err(ResponseError::NXDomain) => (Err(ResponseError::NXDomain), negative_ttl(response)),
+            Err(_) => return,
+        };
+        let Some(ttl) = ttl.filter(|&ttl| ttl > 0) else {
+            return;
+        };
+        // The records that go with a negative result may be served no longer than it is.
+        let authority = match result {
+            Ok(ref answers) if !answers.is_empty() => Vec::new(),
+            _ => response
+                .authority
+                .iter()
+                .map(|record| Record {
+                    ttl: record.ttl.min(ttl),
+                    ..record.clone()
+                })
+                .collect(),
+        };
+        let key = (question.name.clone(), question.r#type, question.class);
+        let entry = Entry {
+            result,
+            authority,
+            authenticated: response.header.flags.ad,
+            inserted: Instant::now(),
+            ttl: Duration::from_secs(ttl.into()),
+        };
+        let mut state = self.state.lock().unwrap();
+        if !state.entries.contains_key(&key) && state.entries.len() >= self.capacity {
+            state.entries.retain(|_, entry| !entry.expired());
+            if state.entries.len() >= self.capacity {
+                let soonest = state
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.inserted + entry.ttl)
+                    .map(|(key, _)| key.clone());
+                if let Some(soonest) = soonest {
+                    state.entries.remove(&soonest);
+                }
+            }
+        }
+        if self.capacity > 0 {
+            state.entries.insert(key, entry);
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.state.lock().unwrap().stats
+    }
+}
+
+impl Entry {
+    fn expired(&self) -> bool {
+        self.inserted.elapsed() >= self.ttl
+    }
+}
+
+// How long a negative response may be cached: the smaller of the TTL of the SOA record in the
+// authority section and its MINIMUM field.
+fn negative_ttl(response: &Message) -> Option<u32> {
+    response.authority.iter().find_map(|r| match r.rdata {
+        RData::SOA { minimum, .. } => Some(r.ttl.min(minimum)),
+        _ => None,
+    })
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} hits, {} misses", self.hits, self.misses)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A response to example.com A from the given answer and authority records, in wire format
+    // after the question.
+    fn response(rcode: u8, answers: &[u8], ancount: u8, authority: &[u8], nscount: u8) -> Message {
+        let mut buf = vec![
+            0x12,
+            0x34,
+            0x81,
+            0x80 | rcode,
+            0,
+            1,
+            0,
+            ancount,
+            0,
+            nscount,
+            0,
+            0,
+        ];
+        buf.extend(b"\x07example\x03com\x00\x00\x01\x00\x01");
+        buf.extend(answers);
+        buf.extend(authority);
+        Message::try_from(buf.as_slice()).unwrap()
+    }
+
+    // An A record for example.com with the given TTL.
+    fn a_record(ttl: u8) -> Vec<u8> {
+        let mut record = b"\xc0\x0c\x00\x01\x00\x01\x00\x00\x00".to_vec();
+        record.extend([ttl, 0, 4, 192, 0, 2, 1]);
+        record
+    }
+
+    // An SOA record for example.com with a TTL of 3600 and the given MINIMUM.
+    fn soa_record(minimum: u8) -> Vec<u8> {
+        let mut record = b"\xc0\x0c\x00\x06\x00\x01\x00\x00\x0e\x10\x00\x20".to_vec();
+        record.extend(b"\x02ns\xc0\x0c\x04root\xc0\x0c");
+        record.extend([
+            0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0, minimum,
+        ]);
+        record
+    }
+
+    fn name() -> Name {
+        "example.com".parse().unwrap()
+    }
+
+    #[test]
+    fn cache_answers() {
+        let cache = Cache::default();
+        assert!(cache.get(&name(), RecordType::A, 1).is_none());
+
+        cache.insert(&response(0, &a_record(60), 1, &[], 0));
+        let records = cache.get(&name(), RecordType::A, 1).unwrap().unwrap();
+        assert_eq!(records[0].rdata.to_string(), "192.0.2.1");
+        assert!(records[0].ttl <= 60);
+        assert!(cache.get(&name(), RecordType::AAAA, 1).is_none());
+        assert_eq!(cache.stats(), Stats { hits: 1, misses: 2 });
+
+        // The AD bit is kept with the answers.
+        let mut response = response(0, &a_record(60), 1, &[], 0);
+        response.header.flags.ad = true;
+        cache.insert(&response);
+        assert!(
+            cache
+                .lookup(&name(), RecordType::A, 1)
+                .unwrap()
+                .authenticated
+        );
+    }
+
+    #[test]
+    fn skip_zero_ttl() {
+        let cache = Cache::default();
+        cache.insert(&response(0, &a_record(0), 1, &[], 0));
+        assert!(cache.get(&name(), RecordType::A, 1).is_none());
+        assert_eq!(cache.stats(), Stats { hits: 0, misses: 1 });
+    }
+
+    #[test]
+    fn cache_nxdomain() {
+        let cache = Cache::default();
+        cache.insert(&response(3, &[], 0, &soa_record(30), 1));
+        assert!(matches!(
+            cache.get(&name(), RecordType::A, 1),
+            Some(Err(ResponseError::NXDomain))
+        ));
+        // The SOA is kept with it, for no longer than the result.
+        let cached = cache.lookup(&name(), RecordType::A, 1).unwrap();
+        assert_eq!(cached.authority[0].r#type, RecordType::SOA);
+        assert!(cached.authority[0].ttl <= 30);
+        assert!(!cached.authenticated);
+
+        // Without an SOA record there is no negative TTL, so nothing is cached.
+        let cache = Cache::default();
+        cache.insert(&response(3, &[], 0, &[], 0));
+        assert!(cache.get(&name(), RecordType::A, 1).is_none());
+
+        // A MINIMUM of zero means the result must not be cached.
+        cache.insert(&response(3, &[], 0, &soa_record(0), 1));
+        assert!(cache.get(&name(), RecordType::A, 1).is_none());
+    }
+
+    #[test]
+    fn evict_when_full() {
+        let cache = Cache::new(2);
+        for (name, ttl) in [("a.example", 60), ("b.example", 30), ("c.example", 90)] {
+            let mut response = response(0, &a_record(ttl), 1, &[], 0);
+            response.questions[0].name = name.parse().unwrap();
+            cache.insert(&response);
+        }
+        // The result closest to expiring made room for the last one.
+        let cached = |name: &str| {
+            cache
+                .get(&name.parse().unwrap(), RecordType::A, 1)
+                .is_some()
+        };
+        assert!(cached("a.example"));
+        assert!(!cached("b.example"));
+        assert!(cached("c.example"));
+        assert_eq!(cache.state.lock().unwrap().entries.len(), 2);
+    }
+
+    #[test]
+    fn cache_nodata() {
+        let cache = Cache::default();
+        cache.insert(&response(0, &[], 0, &soa_record(30), 1));
+        assert_eq!(
+            cache.get(&name(), RecordType::A, 1).unwrap().unwrap().len(),
+            0
+        );
+    }
+}
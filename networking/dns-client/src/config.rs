@@ -0,0 +1,98 @@
+use anyhow::{anyhow, bail, Error};
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+
+// Port nameservers listen on when none is given.
+pub const DNS_PORT: u16 = 53;
+
+// Location of the system resolver configuration.
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+// Parse a nameserver address given on the command line. Accepts a bare IPv4 or IPv6 address, or
+// one with a port in the usual SocketAddr syntax (1.1.1.1:5353 or [::1]:5353).
+pub fn parse_server(s: &str) -> Result<SocketAddr, Error> {
+    parse_server_with_port(s, DNS_PORT)
+}
+
+// Parse a nameserver address like parse_server, using the given port when there is none, e.g.
+// for a server that speaks DNS-over-TLS.
+pub fn parse_server_with_port(s: &str, default_port: u16) -> Result<SocketAddr, Error> {
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let ip = s
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .unwrap_or(s)
+        .parse::<IpAddr>()
+        .map_err(|_| anyhow!("invalid nameserver address {s}"))?;
+    Ok(SocketAddr::new(ip, default_port))
+}
+
+// Read the nameservers configured in /etc/resolv.conf, in the order they are listed.
+pub fn system_servers() -> Result<Vec<SocketAddr>, Error> {
+    let contents = fs::read_to_string(RESOLV_CONF)
+        .map_err(|err| anyhow!("could not read {RESOLV_CONF}: {err}"))?;
+    let servers = parse_resolv_conf(&contents);
+    if servers.is_empty() {
+        bail!("no nameservers found in {RESOLV_CONF}");
+    }
+    Ok(servers)
+}
+
+// Collect the addresses of all `nameserver` lines. Lines we can't parse (such as link-local IPv6
+// addresses with a zone index) are skipped, the same way the system resolver ignores them.
+fn parse_resolv_conf(contents: &str) -> Vec<SocketAddr> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some("nameserver"), Some(addr)) => addr.parse::<IpAddr>().ok(),
+                _ => None,
+            }
+        })
+        .map(|ip| SocketAddr::new(ip, DNS_PORT))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_server_addresses() {
+        assert_eq!(
+            parse_server("1.1.1.1").unwrap(),
+            "1.1.1.1:53".parse().unwrap()
+        );
+        assert_eq!(
+            parse_server("10.0.0.1:5353").unwrap(),
+            "10.0.0.1:5353".parse().unwrap()
+        );
+        assert_eq!(parse_server("::1").unwrap(), "[::1]:53".parse().unwrap());
+        assert_eq!(parse_server("[::1]").unwrap(), "[::1]:53".parse().unwrap());
+        assert_eq!(
+            parse_server("[::1]:5353").unwrap(),
+            "[::1]:5353".parse().unwrap()
+        );
+        assert!(parse_server("example.com").is_err());
+        assert_eq!(
+            parse_server_with_port("[::1]", 853).unwrap(),
+            "[::1]:853".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_nameserver_lines() {
+        let contents = "# comment\nsearch example.com\nnameserver 10.0.0.1\n\
+                        nameserver fe80::1%eth0\nnameserver  2001:db8::53\noptions ndots:2\n";
+        assert_eq!(
+            parse_resolv_conf(contents),
+            [
+                "10.0.0.1:53".parse::<SocketAddr>().unwrap(),
+                "[2001:db8::53]:53".parse().unwrap(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1356 @@
+use crate::edns::Edns;
+use crate::error::ResponseError;
+use crate::message::{Message, Record};
+use crate::name::Name;
+use crate::output::base32hex;
+use crate::query::Query;
+use crate::rdata::{RData, RecordType};
+use crate::resolver::Response;
+use crate::transport::{self, Options};
+use anyhow::{anyhow, bail, Context, Error};
+use ring::digest;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// The DS records of the root zone's key signing keys, as published by IANA at
+// https://data.iana.org/root-anchors/root-anchors.xml (KSK-2017 and KSK-2024).
+const ROOT_ANCHORS: [&str; 2] = [
+    ". IN DS 20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D",
+    ". IN DS 38696 8 2 683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16",
+];
+
+// Upper bound on the DS and DNSKEY queries sent to validate a single response. Every zone on the
+// way down from a trust anchor costs two queries, so this is plenty for real names while stopping
+// runaway validation of deep or looping chains.
+const MAX_QUERIES: usize = 64;
+
+// NSEC3 hashes with more iterations than this are treated as insecure rather than computed
+// (RFC 9276 section 3.2).
+const MAX_NSEC3_ITERATIONS: u16 = 500;
+
+// Flags of a DNSKEY record (RFC 4034 section 2.1.1). Only keys with the zone key flag may sign
+// records.
+const ZONE_KEY: u16 = 0x0100;
+
+// The opt-out flag of an NSEC3 record (RFC 5155 section 3.1.2.1): unsigned delegations may lie
+// between its owner and next hashed name.
+const OPT_OUT: u8 = 0x01;
+
+// A DS record for a zone whose keys are trusted without asking its parent, such as the root.
+#[derive(Debug, Clone)]
+pub struct TrustAnchor {
+    pub zone: Name,
+    pub ds: RData,
+}
+
+// Parse a DS record in master file format, like the ones in ROOT_ANCHORS. The TTL and class are
+// optional, and the digest may be split by whitespace:
+//
+//     example.com. IN DS 370 13 2 BE74359954660069D5C63D200C39F560 3827D7DD02B56F120EE9F3A86764247C
+impl FromStr for TrustAnchor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow!("invalid trust anchor {s:?}");
+        let mut fields = s.split_whitespace().peekable();
+        let zone: Name = fields.next().ok_or_else(invalid)?.parse()?;
+        fields.next_if(|f| f.parse::<u32>().is_ok());
+        fields.next_if(|f| f.eq_ignore_ascii_case("IN"));
+        if !fields.next().is_some_and(|f| f.eq_ignore_ascii_case("DS")) {
+            bail!("trust anchor {s:?} is not a DS record");
+        }
+        let key_tag = fields.next().ok_or_else(invalid)?.parse()?;
+        let algorithm = fields.next().ok_or_else(invalid)?.parse()?;
+        let digest_type = fields.next().ok_or_else(invalid)?.parse()?;
+        let digest = decode_hex(&fields.collect::<String>()).ok_or_else(invalid)?;
+        if digest.is_empty() {
+            return Err(invalid());
+        }
+        Ok(TrustAnchor {
+            zone,
+            ds: RData::DS {
+                key_tag,
+                algorithm,
+                digest_type,
+                digest,
+            },
+        })
+    }
+}
+
+// The trust anchors of the root zone, which cover every name.
+pub fn root_anchors() -> Vec<TrustAnchor> {
+    ROOT_ANCHORS
+        .iter()
+        .map(|s| s.parse().expect("valid root anchor"))
+        .collect()
+}
+
+// The outcome of validating an RRset (RFC 4035 section 4.3).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    // There is a chain of valid signatures from a trust anchor down to the RRset.
+    Secure,
+    // A zone on the way down proved it isn't signed, so the RRset can't be checked.
+    Insecure,
+    // The RRset is validly signed, but the answer also depends on a proof of nonexistence that
+    // isn't checked: that the name or type doesn't exist for a negative response, or that no
+    // closer match exists for a wildcard answer. The reason says which.
+    Indeterminate(String),
+    // The RRset should have been signed but no valid signature leads to it. The reason says
+    // which link of the chain is broken.
+    Bogus(String),
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Secure => write!(f, "secure"),
+            Status::Insecure => write!(f, "insecure"),
+            Status::Indeterminate(reason) => write!(f, "indeterminate ({reason})"),
+            Status::Bogus(reason) => write!(f, "bogus ({reason})"),
+        }
+    }
+}
+
+// The status of one RRset of the response.
+#[derive(Debug, Clone)]
+pub struct RRsetStatus {
+    pub name: Name,
+    pub r#type: RecordType,
+    pub status: Status,
+}
+
+// A response along with the status of each of its RRsets: those in the answer section, or the
+// authority section of a negative response. Only the RRsets themselves are validated, so the
+// RRsets of negative responses and wildcard answers are at best Indeterminate, as the NSEC or
+// NSEC3 proofs they rely on aren't checked. A negative response without any records to check is
+// Bogus unless the name is in an unsigned zone.
+pub struct Validated {
+    pub response: Response,
+    pub rrsets: Vec<RRsetStatus>,
+}
+
+impl Validated {
+    // Whether the response can't be trusted: an RRset is Bogus, or is Indeterminate because the
+    // response rests on a proof that wasn't checked. A replayed or forged denial looks just like
+    // a real one until its proof is checked, so it must not pass for one.
+    pub fn failed(&self) -> bool {
+        self.rrsets
+            .iter()
+            .any(|rrset| matches!(rrset.status, Status::Bogus(_) | Status::Indeterminate(_)))
+    }
+}
+
+// Look up a name and validate the records in the response with DNSSEC (RFC 4035 section 5).
+// The nameservers are expected to be recursive resolvers: every query asks for DNSSEC records
+// with the DO bit, and sets the CD bit so the resolver hands over records even when its own
+// validation fails. The chain of trust is then rebuilt here from the DS and DNSKEY records of
+// every zone between a trust anchor and the answer.
+pub fn validate(
+    domain: &str,
+    r#type: RecordType,
+    servers: &[SocketAddr],
+    options: &Options,
+    anchors: &[TrustAnchor],
+) -> Result<Validated, Error> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32;
+    let mut validator = Validator {
+        servers,
+        options,
+        anchors,
+        now,
+        zones: HashMap::new(),
+        in_progress: HashSet::new(),
+        queries: 0,
+    };
+    let start = Instant::now();
+    let (message, server) = validator.query(domain, r#type)?;
+    let elapsed = start.elapsed();
+
+    let negative = message.answers.is_empty();
+    let section = match negative {
+        true => &message.authority,
+        false => &message.answers,
+    };
+    let mut rrsets = Vec::new();
+    for (name, rrset_type, records) in group_rrsets(section) {
+        let signatures = signatures(section, &name, rrset_type);
+        let status = match validator.rrset_status(&records, &signatures)? {
+            Status::Secure if negative => Status::Indeterminate(format!(
+                "the proof that {domain} {type} does not exist is not checked"
+            )),
+            Status::Secure if signatures.iter().any(|s| from_wildcard(&name, s)) => {
+                Status::Indeterminate(format!(
+                    "{name} {rrset_type} comes from a wildcard and the proof that no closer \
+                     match exists is not checked"
+                ))
+            }
+            status => status,
+        };
+        rrsets.push(RRsetStatus {
+            name,
+            r#type: rrset_type,
+            status,
+        });
+    }
+    if negative && rrsets.is_empty() {
+        let name: Name = domain.parse()?;
+        let status = match validator.closest_zone(&name)?.1 {
+            Keys::Insecure => Status::Insecure,
+            Keys::Bogus(reason) => Status::Bogus(reason),
+            Keys::Secure(_) | Keys::NotZone => Status::Bogus(format!(
+                "{name} is in a signed zone but the response has no proof that it has no {type} records"
+            )),
+        };
+        rrsets.push(RRsetStatus {
+            name,
+            r#type,
+            status,
+        });
+    }
+    Ok(Validated {
+        response: Response {
+            message,
+            server,
+            elapsed,
+        },
+        rrsets,
+    })
+}
+
+// What is known about the keys of a name.
+#[derive(Debug, Clone)]
+enum Keys {
+    // The name is a signed zone with these DNSKEY records, whose signatures have been checked.
+    Secure(Vec<RData>),
+    // The name is in (or is) a zone that is provably unsigned.
+    Insecure,
+    // The chain of trust to the name is broken.
+    Bogus(String),
+    // The name provably isn't a zone cut, so its records are signed by an ancestor.
+    NotZone,
+}
+
+struct Validator<'a> {
+    servers: &'a [SocketAddr],
+    options: &'a Options,
+    anchors: &'a [TrustAnchor],
+    // Seconds since the Unix epoch, to check signature validity periods against.
+    now: u32,
+    // Keys of every name looked at so far.
+    zones: HashMap<Name, Keys>,
+    // Names whose keys are being looked up, to stop a signature chain that loops back on itself.
+    in_progress: HashSet<Name>,
+    queries: usize,
+}
+
+impl Validator<'_> {
+    fn query(&mut self, domain: &str, r#type: RecordType) -> Result<(Message, SocketAddr), Error> {
+        self.queries += 1;
+        if self.queries > MAX_QUERIES {
+            bail!("gave up on DNSSEC validation after {MAX_QUERIES} queries");
+        }
+        let query = Query {
+            edns: Some(Edns {
+                dnssec_ok: true,
+                ..Edns::default()
+            }),
+            checking_disabled: true,
+            ..Query::new(domain, r#type)
+        };
+        // NXDOMAIN responses carry proofs of nonexistence, so they are validated like any other.
+        let context = || format!("{type} lookup for {domain} failed");
+        let (response, server) =
+            transport::exchange(&query, self.servers, self.options).with_context(context)?;
+        match response.check_rcode() {
+            Ok(()) | Err(ResponseError::NXDomain) => Ok((response, server)),
+            Err(err) => Err(Error::from(err).context(context())),
+        }
+    }
+
+    // An RRset is secure if any of its signatures was made by a secure key of a zone that the
+    // RRset belongs to. Below a trust anchor, only zones at or below the closest anchor count:
+    // nothing above it is trusted, and treating a signer up there as unsigned would let a forged
+    // signature turn bogus records into insecure ones.
+    fn rrset_status(
+        &mut self,
+        records: &[&Record],
+        signatures: &[&Record],
+    ) -> Result<Status, Error> {
+        let owner = &records[0].name;
+        let anchor = self
+            .anchors
+            .iter()
+            .map(|a| &a.zone)
+            .filter(|zone| owner.is_subdomain_of(zone))
+            .max_by_key(|zone| zone.labels().len())
+            .cloned();
+        let mut reason = format!("no usable signature for {owner} {}", records[0].r#type);
+        for signature in signatures {
+            let RData::RRSIG {
+                signer,
+                key_tag,
+                algorithm,
+                ..
+            } = &signature.rdata
+            else {
+                continue;
+            };
+            if !owner.is_subdomain_of(signer) || self.in_progress.contains(signer) {
+                continue;
+            }
+            if let Some(anchor) = anchor.as_ref().filter(|a| !signer.is_subdomain_of(a)) {
+                reason = format!("signer {signer} of {owner} is above the trust anchor {anchor}");
+                continue;
+            }
+            let keys = match self.zone_keys(signer)? {
+                Keys::Secure(keys) => keys,
+                Keys::Insecure => return Ok(Status::Insecure),
+                Keys::Bogus(why) => {
+                    reason = why;
+                    continue;
+                }
+                Keys::NotZone => {
+                    reason = format!("signer {signer} is not a zone");
+                    continue;
+                }
+            };
+            let candidates = keys.iter().filter(|key| match key {
+                RData::DNSKEY { algorithm: a, .. } => {
+                    a == algorithm && key_tag_of(key) == Some(*key_tag)
+                }
+                _ => false,
+            });
+            for key in candidates {
+                match verify(records, signature, key, self.now) {
+                    Ok(()) => return Ok(Status::Secure),
+                    Err(err) => reason = format!("{owner} {}: {err}", records[0].r#type),
+                }
+            }
+        }
+        if signatures.is_empty() {
+            return self.unsigned_status(owner);
+        }
+        Ok(Status::Bogus(reason))
+    }
+
+    // Records without signatures are fine only if they are in an unsigned zone.
+    fn unsigned_status(&mut self, owner: &Name) -> Result<Status, Error> {
+        Ok(match self.closest_zone(owner)?.1 {
+            Keys::Secure(_) | Keys::NotZone => {
+                Status::Bogus(format!("{owner} is in a signed zone but has no signatures"))
+            }
+            Keys::Insecure => Status::Insecure,
+            Keys::Bogus(reason) => Status::Bogus(reason),
+        })
+    }
+
+    // The zone a name belongs to and its keys: the name itself if it's a zone cut, or else the
+    // closest ancestor that is.
+    fn closest_zone(&mut self, name: &Name) -> Result<(Name, Keys), Error> {
+        let mut name = name.clone();
+        loop {
+            match self.zone_keys(&name)? {
+                Keys::NotZone => {}
+                keys => return Ok((name, keys)),
+            }
+            name = name.parent().ok_or(anyhow!("the root is not a zone"))?;
+        }
+    }
+
+    // Find out whether a name is a zone cut and if so, what its keys are: its parent vouches for
+    // its DNSKEYs with DS records, or proves there aren't any. Names not covered by a trust anchor
+    // are insecure.
+    fn zone_keys(&mut self, zone: &Name) -> Result<Keys, Error> {
+        if let Some(keys) = self.zones.get(zone) {
+            return Ok(keys.clone());
+        }
+        if !self.anchors.iter().any(|a| zone.is_subdomain_of(&a.zone)) {
+            return Ok(Keys::Insecure);
+        }
+        if !self.in_progress.insert(zone.clone()) {
+            return Ok(Keys::Bogus(format!("signatures of {zone} form a loop")));
+        }
+        let keys = self.find_zone_keys(zone);
+        self.in_progress.remove(zone);
+        let keys = keys?;
+        self.zones.insert(zone.clone(), keys.clone());
+        Ok(keys)
+    }
+
+    fn find_zone_keys(&mut self, zone: &Name) -> Result<Keys, Error> {
+        let anchors: Vec<RData> = self
+            .anchors
+            .iter()
+            .filter(|a| &a.zone == zone)
+            .map(|a| a.ds.clone())
+            .collect();
+        if !anchors.is_empty() {
+            return self.verify_dnskeys(zone, &anchors);
+        }
+        let parent = zone
+            .parent()
+            .ok_or(anyhow!("no trust anchor for the root"))?;
+        let parent = match self.closest_zone(&parent)? {
+            (parent, Keys::Secure(_)) => parent,
+            (_, keys) => return Ok(keys),
+        };
+
+        // The parent is signed, so its answer about DS records for the name must be too.
+        let (response, _) = self.query(&zone.to_string(), RecordType::DS)?;
+        let ds = records_of(&response.answers, zone, RecordType::DS);
+        if ds.is_empty() {
+            return self.no_ds(zone, &parent, &response);
+        }
+        let signatures = signatures(&response.answers, zone, RecordType::DS);
+        match self.rrset_status(&ds, &signatures)? {
+            Status::Secure => {}
+            Status::Insecure => return Ok(Keys::Bogus(format!("DS of {zone} is not signed"))),
+            Status::Bogus(reason) | Status::Indeterminate(reason) => {
+                return Ok(Keys::Bogus(reason))
+            }
+        }
+        let ds: Vec<RData> = ds.iter().map(|r| r.rdata.clone()).collect();
+        self.verify_dnskeys(zone, &ds)
+    }
+
+    // Check that the zone's DNSKEY RRset is signed by a key matching one of the DS records.
+    fn verify_dnskeys(&mut self, zone: &Name, ds: &[RData]) -> Result<Keys, Error> {
+        // A zone whose DS records all use algorithms we don't know is treated as unsigned (RFC 4035
+        // section 5.2).
+        let ds: Vec<&RData> = ds
+            .iter()
+            .filter(|ds| match ds {
+                RData::DS {
+                    algorithm,
+                    digest_type,
+                    ..
+                } => supports_algorithm(*algorithm) && digest_algorithm(*digest_type).is_some(),
+                _ => false,
+            })
+            .collect();
+        if ds.is_empty() {
+            return Ok(Keys::Insecure);
+        }
+
+        let (response, _) = self.query(&zone.to_string(), RecordType::DNSKEY)?;
+        let dnskeys = records_of(&response.answers, zone, RecordType::DNSKEY);
+        if dnskeys.is_empty() {
+            return Ok(Keys::Bogus(format!("{zone} has no DNSKEY records")));
+        }
+        let signatures = signatures(&response.answers, zone, RecordType::DNSKEY);
+        let mut reason = format!("no DNSKEY of {zone} matching its DS records signs the DNSKEYs");
+        for key in &dnskeys {
+            if !ds.iter().any(|ds| matches_ds(zone, &key.rdata, ds)) {
+                continue;
+            }
+            for signature in &signatures {
+                match &signature.rdata {
+                    RData::RRSIG { key_tag, .. } if Some(*key_tag) == key_tag_of(&key.rdata) => {}
+                    _ => continue,
+                }
+                match verify(&dnskeys, signature, &key.rdata, self.now) {
+                    Ok(()) => {
+                        let keys = dnskeys.iter().map(|r| r.rdata.clone()).collect();
+                        return Ok(Keys::Secure(keys));
+                    }
+                    Err(err) => reason = format!("DNSKEY of {zone}: {err}"),
+                }
+            }
+        }
+        Ok(Keys::Bogus(reason))
+    }
+
+    // Work out what a signed proof that a name has no DS records means (RFC 4035 section 5.2 and
+    // RFC 5155 section 8.6): a delegation without DS records is an unsigned zone, while a name
+    // without NS records, or one that doesn't exist at all, isn't a zone cut.
+    //
+    // Only the parent zone can prove that, so the NSEC or NSEC3 records must be in it and signed
+    // by it. A proof from any other signed zone would otherwise pass for one, and turn the name
+    // into an unsigned zone. For NSEC3 this only looks for a record matching or covering the name
+    // itself, rather than checking the full closest encloser proof.
+    fn no_ds(&mut self, zone: &Name, parent: &Name, response: &Message) -> Result<Keys, Error> {
+        let authority = &response.authority;
+        let mut reason = format!("no proof that {zone} has no DS records");
+        for (owner, r#type, records) in group_rrsets(authority) {
+            let in_parent = match r#type {
+                RecordType::NSEC => owner.is_subdomain_of(parent),
+                // NSEC3 owners are a hash directly under the zone's apex.
+                RecordType::NSEC3 => owner.parent().as_ref() == Some(parent),
+                _ => continue,
+            };
+            let signatures: Vec<&Record> = signatures(authority, &owner, r#type)
+                .into_iter()
+                .filter(|s| matches!(&s.rdata, RData::RRSIG { signer, .. } if signer == parent))
+                .collect();
+            if !in_parent || signatures.is_empty() {
+                reason =
+                    format!("{type} record {owner} is not from {parent}, the parent of {zone}");
+                continue;
+            }
+            let proof = match r#type {
+                RecordType::NSEC => nsec_proof(zone, &owner, &records),
+                _ => nsec3_proof(zone, &owner, &records),
+            };
+            let Some(proof) = proof else {
+                continue;
+            };
+            match self.rrset_status(&records, &signatures)? {
+                Status::Secure => return Ok(proof),
+                Status::Insecure => reason = format!("{type} proof for {zone} is not signed"),
+                Status::Bogus(why) | Status::Indeterminate(why) => reason = why,
+            }
+        }
+        Ok(Keys::Bogus(reason))
+    }
+}
+
+// What an NSEC RRset says about the DS records of a name, if anything.
+fn nsec_proof(zone: &Name, owner: &Name, records: &[&Record]) -> Option<Keys> {
+    let RData::NSEC { next, types } = &records.first()?.rdata else {
+        return None;
+    };
+    if owner == zone {
+        return Some(delegation_proof(zone, types));
+    }
+    covers(owner, next, zone, canonical_cmp).then_some(Keys::NotZone)
+}
+
+// What an NSEC3 RRset says about the DS records of a name, if anything. The hashes are compared
+// in their base32hex form, which sorts the same way as the raw hashes. The RRset must already be
+// known to belong to the name's parent zone, since too many iterations make the name insecure
+// without looking at the hash at all.
+fn nsec3_proof(zone: &Name, owner: &Name, records: &[&Record]) -> Option<Keys> {
+    let RData::NSEC3 {
+        hash_algorithm,
+        flags,
+        iterations,
+        salt,
+        next_hashed,
+        types,
+    } = &records.first()?.rdata
+    else {
+        return None;
+    };
+    // Only SHA-1 is defined.
+    if *hash_algorithm != 1 {
+        return None;
+    }
+    if *iterations > MAX_NSEC3_ITERATIONS {
+        return Some(Keys::Insecure);
+    }
+    let owner_hash = String::from_utf8_lossy(owner.labels().first()?).to_ascii_lowercase();
+    let hash = base32hex(&nsec3_hash(zone, salt, *iterations));
+    if owner_hash == hash {
+        return Some(delegation_proof(zone, types));
+    }
+    let next = base32hex(next_hashed);
+    if !covers(&owner_hash, &next, &hash, Ord::cmp) {
+        return None;
+    }
+    match flags & OPT_OUT {
+        0 => Some(Keys::NotZone),
+        _ => Some(Keys::Insecure),
+    }
+}
+
+// What the types at a name say about its DS records.
+fn delegation_proof(zone: &Name, types: &[RecordType]) -> Keys {
+    let has = |t| types.contains(&t);
+    if has(RecordType::DS) {
+        Keys::Bogus(format!("{zone} has DS records but none were returned"))
+    } else if has(RecordType::SOA) {
+        Keys::Bogus(format!(
+            "proof of no DS for {zone} came from the zone itself"
+        ))
+    } else if has(RecordType::NS) {
+        Keys::Insecure
+    } else {
+        Keys::NotZone
+    }
+}
+
+// Whether a value falls strictly between an NSEC owner and its next name. The last record of a
+// zone wraps around to the first.
+fn covers<T: ?Sized>(owner: &T, next: &T, value: &T, cmp: impl Fn(&T, &T) -> Ordering) -> bool {
+    let after_owner = cmp(owner, value) == Ordering::Less;
+    let before_next = cmp(value, next) == Ordering::Less;
+    match cmp(owner, next) {
+        Ordering::Less => after_owner && before_next,
+        _ => after_owner || before_next,
+    }
+}
+
+// The canonical ordering of names (RFC 4034 section 6.1): compare labels from the rightmost one,
+// as lowercase byte strings.
+fn canonical_cmp(a: &Name, b: &Name) -> Ordering {
+    let labels = |n: &Name| {
+        n.labels()
+            .iter()
+            .rev()
+            .map(|l| l.to_ascii_lowercase())
+            .collect::<Vec<_>>()
+    };
+    labels(a).cmp(&labels(b))
+}
+
+// The hashed owner name NSEC3 uses for a name (RFC 5155 section 5): SHA-1 of the name in
+// canonical wire format and the salt, rehashed with the salt the given number of times.
+fn nsec3_hash(name: &Name, salt: &[u8], iterations: u16) -> Vec<u8> {
+    let mut hash = Vec::new();
+    name.to_lowercase().encode(&mut hash);
+    for _ in 0..=iterations {
+        hash.extend(salt);
+        hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &hash)
+            .as_ref()
+            .to_vec();
+    }
+    hash
+}
+
+// Group records by owner name and type, keeping the order each RRset first appears in. RRSIGs
+// belong to the RRset they cover, so they aren't RRsets themselves.
+fn group_rrsets(records: &[Record]) -> Vec<(Name, RecordType, Vec<&Record>)> {
+    let mut rrsets: Vec<(Name, RecordType, Vec<&Record>)> = Vec::new();
+    for record in records {
+        if matches!(record.r#type, RecordType::RRSIG | RecordType::OPT) {
+            continue;
+        }
+        match rrsets
+            .iter_mut()
+            .find(|(name, r#type, _)| name == &record.name && *r#type == record.r#type)
+        {
+            Some((_, _, rrset)) => rrset.push(record),
+            None => rrsets.push((record.name.clone(), record.r#type, vec![record])),
+        }
+    }
+    rrsets
+}
+
+// Whether a signature says its record was synthesized from a wildcard: it has fewer labels than
+// the owner name, not counting the asterisk of a literal wildcard owner (RFC 4035 section 5.3.4).
+fn from_wildcard(owner: &Name, signature: &Record) -> bool {
+    let RData::RRSIG { labels, .. } = signature.rdata else {
+        return false;
+    };
+    let wildcard = owner.labels().first().is_some_and(|l| l == b"*");
+    (labels as usize) < owner.labels().len() - usize::from(wildcard)
+}
+
+fn records_of<'a>(records: &'a [Record], name: &Name, r#type: RecordType) -> Vec<&'a Record> {
+    records
+        .iter()
+        .filter(|r| &r.name == name && r.r#type == r#type)
+        .collect()
+}
+
+// The RRSIG records covering an RRset.
+fn signatures<'a>(records: &'a [Record], name: &Name, r#type: RecordType) -> Vec<&'a Record> {
+    records
+        .iter()
+        .filter(|r| &r.name == name)
+        .filter(|r| matches!(r.rdata, RData::RRSIG { type_covered, .. } if type_covered == r#type))
+        .collect()
+}
+
+// Check an RRSIG over an RRset with a DNSKEY (RFC 4035 section 5.3), at the given time in seconds
+// since the Unix epoch.
+pub(crate) fn verify(
+    rrset: &[&Record],
+    signature: &Record,
+    key: &RData,
+    now: u32,
+) -> Result<(), Error> {
+    let RData::RRSIG {
+        type_covered,
+        algorithm,
+        labels,
+        expiration,
+        inception,
+        signature: sig,
+        ..
+    } = &signature.rdata
+    else {
+        bail!("not an RRSIG record");
+    };
+    let RData::DNSKEY {
+        flags,
+        protocol,
+        algorithm: key_algorithm,
+        public_key,
+    } = key
+    else {
+        bail!("not a DNSKEY record");
+    };
+    let owner = &rrset.first().ok_or(anyhow!("empty RRset"))?.name;
+    if *type_covered != rrset[0].r#type {
+        bail!("signature covers {type_covered}, not {}", rrset[0].r#type);
+    }
+    if *labels as usize > owner.labels().len() {
+        bail!("signature has more labels than the owner name");
+    }
+    // Timestamps are compared with serial number arithmetic (RFC 1982), so they wrap in 2106.
+    if (now.wrapping_sub(*inception) as i32) < 0 {
+        bail!("signature is not valid yet");
+    }
+    if (expiration.wrapping_sub(now) as i32) < 0 {
+        bail!("signature has expired");
+    }
+    if flags & ZONE_KEY == 0 || *protocol != 3 {
+        bail!("DNSKEY is not a zone key");
+    }
+    if key_algorithm != algorithm {
+        bail!("DNSKEY algorithm {key_algorithm} does not match signature algorithm {algorithm}");
+    }
+    let data = signed_data(rrset, &signature.rdata)?;
+    verify_signature(*algorithm, public_key, &data, sig)
+}
+
+// The data an RRSIG signs (RFC 4034 section 3.1.8.1): the RRSIG's own fields up to and
+// including the signer's name, followed by every record of the RRset in canonical form and
+// order (sections 6.2 and 6.3).
+pub(crate) fn signed_data(rrset: &[&Record], rrsig: &RData) -> Result<Vec<u8>, Error> {
+    let RData::RRSIG {
+        labels,
+        original_ttl,
+        signer,
+        ..
+    } = rrsig
+    else {
+        bail!("not an RRSIG record");
+    };
+    let mut data = Vec::new();
+    rrsig.encode_rrsig_fields(&mut data);
+    signer.to_lowercase().encode(&mut data);
+
+    let mut records = Vec::new();
+    for record in rrset {
+        // A record synthesized from a wildcard is signed under the wildcard name, which has the
+        // number of labels given in the RRSIG plus the asterisk.
+        let owner = record.name.to_lowercase();
+        let extra = owner
+            .labels()
+            .len()
+            .checked_sub(*labels as usize)
+            .ok_or(anyhow!("signature has more labels than the owner name"))?;
+        let owner = match extra {
+            0 => owner,
+            extra => {
+                let mut wildcard = vec![b"*".to_vec()];
+                wildcard.extend(owner.labels()[extra..].iter().cloned());
+                Name::from_labels(wildcard)?
+            }
+        };
+        let rdata = canonical_rdata(&record.rdata)?;
+        let mut wire = Vec::new();
+        owner.encode(&mut wire);
+        wire.extend(u16::from(record.r#type).to_be_bytes());
+        wire.extend(record.class.to_be_bytes());
+        wire.extend(original_ttl.to_be_bytes());
+        wire.extend(u16::try_from(rdata.len())?.to_be_bytes());
+        records.push((rdata, wire));
+    }
+    records.sort();
+    records.dedup();
+    for (rdata, wire) in records {
+        data.extend(wire);
+        data.extend(rdata);
+    }
+    Ok(data)
+}
+
+// RDATA in canonical form: uncompressed, with the names in the types listed in RFC 4034 section
+// 6.2 (as amended by RFC 6840 section 5.1) lowercased.
+fn canonical_rdata(rdata: &RData) -> Result<Vec<u8>, Error> {
+    let mut rdata = rdata.clone();
+    match &mut rdata {
+        RData::NS(name)
+        | RData::CNAME(name)
+        | RData::PTR(name)
+        | RData::MX { exchange: name, .. }
+        | RData::SRV { target: name, .. }
+        | RData::RRSIG { signer: name, .. } => *name = name.to_lowercase(),
+        RData::SOA { mname, rname, .. } => {
+            *mname = mname.to_lowercase();
+            *rname = rname.to_lowercase();
+        }
+        _ => {}
+    }
+    let mut buf = Vec::new();
+    rdata.encode(&mut buf)?;
+    Ok(buf)
+}
+
+// The key tag of a DNSKEY (RFC 4034 appendix B): a checksum of its RDATA that signatures and DS
+// records use to point at it.
+pub(crate) fn key_tag_of(key: &RData) -> Option<u16> {
+    if !matches!(key, RData::DNSKEY { .. }) {
+        return None;
+    }
+    let mut rdata = Vec::new();
+    key.encode(&mut rdata).ok()?;
+    let mut sum: u32 = 0;
+    for (i, &b) in rdata.iter().enumerate() {
+        sum += match i % 2 {
+            0 => (b as u32) << 8,
+            _ => b as u32,
+        };
+    }
+    sum += sum >> 16;
+    Some(sum as u16)
+}
+
+// Whether a DNSKEY is the one a DS record points at: the DS digest is a hash of the owner name
+// and the DNSKEY RDATA (RFC 4034 section 5.1.4).
+fn matches_ds(zone: &Name, key: &RData, ds: &RData) -> bool {
+    let RData::DS {
+        key_tag,
+        algorithm,
+        digest_type,
+        digest,
+    } = ds
+    else {
+        return false;
+    };
+    let (RData::DNSKEY { algorithm: a, .. }, Some(tag)) = (key, key_tag_of(key)) else {
+        return false;
+    };
+    let Some(digest_algorithm) = digest_algorithm(*digest_type) else {
+        return false;
+    };
+    if a != algorithm || tag != *key_tag {
+        return false;
+    }
+    let mut data = Vec::new();
+    zone.to_lowercase().encode(&mut data);
+    if key.encode(&mut data).is_err() {
+        return false;
+    }
+    digest::digest(digest_algorithm, &data).as_ref() == digest.as_slice()
+}
+
+// DS digest types (https://www.iana.org/assignments/ds-rr-types).
+fn digest_algorithm(digest_type: u8) -> Option<&'static digest::Algorithm> {
+    match digest_type {
+        1 => Some(&digest::SHA1_FOR_LEGACY_USE_ONLY),
+        2 => Some(&digest::SHA256),
+        4 => Some(&digest::SHA384),
+        _ => None,
+    }
+}
+
+// DNSSEC algorithm numbers (https://www.iana.org/assignments/dns-sec-alg-numbers) that
+// verify_signature handles.
+fn supports_algorithm(algorithm: u8) -> bool {
+    matches!(algorithm, 8 | 10 | 13 | 14 | 15)
+}
+
+fn verify_signature(
+    algorithm: u8,
+    public_key: &[u8],
+    data: &[u8],
+    sig: &[u8],
+) -> Result<(), Error> {
+    // ECDSA keys are the two coordinates of the point without the uncompressed point prefix
+    // (RFC 6605 section 4), and signatures are r and s back to back.
+    let ecdsa = |params| {
+        let mut point = vec![0x04];
+        point.extend(public_key);
+        UnparsedPublicKey::new(params, point).verify(data, sig)
+    };
+    let result = match algorithm {
+        8 => rsa(public_key)?.verify(
+            &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+            data,
+            sig,
+        ),
+        10 => rsa(public_key)?.verify(
+            &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY,
+            data,
+            sig,
+        ),
+        13 => ecdsa(&signature::ECDSA_P256_SHA256_FIXED),
+        14 => ecdsa(&signature::ECDSA_P384_SHA384_FIXED),
+        15 => UnparsedPublicKey::new(&signature::ED25519, public_key).verify(data, sig),
+        _ => bail!("unsupported algorithm {algorithm}"),
+    };
+    result.map_err(|_| anyhow!("invalid signature"))
+}
+
+// Split an RSA public key into its exponent and modulus (RFC 3110 section 2). The exponent's
+// length comes first, in one byte, or in the two bytes after a zero byte if it's longer than 255.
+fn rsa(key: &[u8]) -> Result<RsaPublicKeyComponents<&[u8]>, Error> {
+    let (length, rest) = match key {
+        [0, a, b, rest @ ..] => (u16::from_be_bytes([*a, *b]) as usize, rest),
+        [length, rest @ ..] => (*length as usize, rest),
+        [] => bail!("empty RSA key"),
+    };
+    if length == 0 || rest.len() <= length {
+        bail!("invalid RSA key");
+    }
+    let (e, n) = rest.split_at(length);
+    Ok(RsaPublicKeyComponents { n, e })
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use std::net::UdpSocket;
+    use std::slice;
+    use std::thread;
+    use std::time::Duration;
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    fn record(owner: &str, ttl: u32, rdata: RData) -> Record {
+        let r#type = match &rdata {
+            RData::A(_) => RecordType::A,
+            RData::NS(_) => RecordType::NS,
+            RData::DS { .. } => RecordType::DS,
+            RData::RRSIG { .. } => RecordType::RRSIG,
+            RData::NSEC { .. } => RecordType::NSEC,
+            RData::NSEC3 { .. } => RecordType::NSEC3,
+            RData::DNSKEY { .. } => RecordType::DNSKEY,
+            rdata => panic!("unexpected {rdata:?}"),
+        };
+        Record {
+            name: name(owner),
+            r#type,
+            class: 1,
+            ttl,
+            rdata,
+        }
+    }
+
+    // The ECDSA P-256 example from RFC 6605 section 6.1.
+    #[test]
+    fn verify_rfc6605_example() {
+        let key = RData::DNSKEY {
+            flags: 257,
+            protocol: 3,
+            algorithm: 13,
+            public_key: STANDARD
+                .decode(
+                    "GojIhhXUN/u4v54ZQqGSnyhWJwaubCvTmeexv7bR6edbkrSqQpF64cYbcB7wNcP+e+MAnLr+Wi9xMWyQLc8NAA==",
+                )
+                .unwrap(),
+        };
+        assert_eq!(key_tag_of(&key), Some(55648));
+        let anchor: TrustAnchor = "example.net. 3600 IN DS 55648 13 2 \
+            b4c8c1fe2e7477127b27115656ad6256f424625bf5c1e2770ce6d6e37df61d17"
+            .parse()
+            .unwrap();
+        assert!(matches_ds(&anchor.zone, &key, &anchor.ds));
+
+        let a = record("www.example.net", 3600, RData::A([192, 0, 2, 1].into()));
+        let rrsig = record(
+            "www.example.net",
+            3600,
+            RData::RRSIG {
+                type_covered: RecordType::A,
+                algorithm: 13,
+                labels: 3,
+                original_ttl: 3600,
+                expiration: 1284026679,
+                inception: 1281607479,
+                key_tag: 55648,
+                signer: name("example.net"),
+                signature: STANDARD
+                    .decode(
+                        "qx6wLYqmh+l9oCKTN6qIc+bw6ya+KJ8oMz0YP107epXAyGmt+3SNruPFKG7tZoLBLlUzGGus7ZwmwWep666VCw==",
+                    )
+                    .unwrap(),
+            },
+        );
+        let during = 1282000000;
+        verify(&[&a], &rrsig, &key, during).unwrap();
+        assert!(verify(&[&a], &rrsig, &key, 1290000000).is_err());
+        let other = record("www.example.net", 3600, RData::A([192, 0, 2, 2].into()));
+        assert!(verify(&[&other], &rrsig, &key, during).is_err());
+    }
+
+    #[test]
+    fn canonical_order() {
+        // The example from RFC 4034 section 6.1.
+        let names = [
+            "example",
+            "a.example",
+            "yljkjljk.a.example",
+            "Z.a.example",
+            "zABC.a.EXAMPLE",
+            "z.example",
+            "\\001.z.example",
+            "*.z.example",
+            "\\200.z.example",
+        ]
+        .map(name);
+        let mut sorted = names.to_vec();
+        sorted.reverse();
+        sorted.sort_by(canonical_cmp);
+        assert_eq!(sorted, names);
+        assert!(covers(&names[1], &names[5], &names[3], canonical_cmp));
+        assert!(!covers(&names[1], &names[5], &names[6], canonical_cmp));
+        // The last NSEC record of a zone points back at the apex.
+        assert!(covers(
+            &names[8],
+            &names[0],
+            &name("zz.example"),
+            canonical_cmp
+        ));
+    }
+
+    #[test]
+    fn parse_trust_anchors() {
+        let anchors = root_anchors();
+        assert_eq!(anchors.len(), 2);
+        assert!(anchors[0].zone.is_root());
+        assert!(matches!(anchors[1].ds, RData::DS { key_tag: 38696, .. }));
+        assert!("example. IN A 192.0.2.1".parse::<TrustAnchor>().is_err());
+        assert!("example. DS 1 13 2 abc".parse::<TrustAnchor>().is_err());
+    }
+
+    // A signing key for a zone in the stand-in hierarchy.
+    struct ZoneKey {
+        zone: &'static str,
+        pair: EcdsaKeyPair,
+        dnskey: RData,
+    }
+
+    impl ZoneKey {
+        fn new(zone: &'static str) -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+            let pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                    .unwrap();
+            let dnskey = RData::DNSKEY {
+                flags: 257,
+                protocol: 3,
+                algorithm: 13,
+                public_key: pair.public_key().as_ref()[1..].to_vec(),
+            };
+            ZoneKey { zone, pair, dnskey }
+        }
+
+        fn ds(&self) -> RData {
+            let mut data = Vec::new();
+            name(self.zone).encode(&mut data);
+            self.dnskey.encode(&mut data).unwrap();
+            RData::DS {
+                key_tag: key_tag_of(&self.dnskey).unwrap(),
+                algorithm: 13,
+                digest_type: 2,
+                digest: digest::digest(&digest::SHA256, &data).as_ref().to_vec(),
+            }
+        }
+
+        // Sign an RRset, valid from an hour ago for a day.
+        fn sign(&self, rrset: &[Record]) -> Record {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as u32;
+            let owner = &rrset[0].name;
+            // The asterisk of a wildcard isn't counted.
+            let wildcard = owner.labels().first().is_some_and(|l| l == b"*");
+            let mut rrsig = RData::RRSIG {
+                type_covered: rrset[0].r#type,
+                algorithm: 13,
+                labels: (owner.labels().len() - usize::from(wildcard)) as u8,
+                original_ttl: rrset[0].ttl,
+                expiration: now + 86400,
+                inception: now - 3600,
+                key_tag: key_tag_of(&self.dnskey).unwrap(),
+                signer: name(self.zone),
+                signature: Vec::new(),
+            };
+            let data = signed_data(&rrset.iter().collect::<Vec<_>>(), &rrsig).unwrap();
+            let sig = self.pair.sign(&SystemRandom::new(), &data).unwrap();
+            if let RData::RRSIG { signature, .. } = &mut rrsig {
+                *signature = sig.as_ref().to_vec();
+            }
+            record(&owner.to_string(), rrset[0].ttl, rrsig)
+        }
+
+        // An RRset along with its signature.
+        fn signed(&self, rrset: Vec<Record>) -> Vec<Record> {
+            let rrsig = self.sign(&rrset);
+            rrset.into_iter().chain([rrsig]).collect()
+        }
+    }
+
+    // Answers and authority records for each question the stand-in resolver knows about.
+    type Zone = HashMap<(Name, RecordType), (Vec<Record>, Vec<Record>)>;
+
+    // A signed root and example. zone, with an unsigned delegation to insecure.example.:
+    //
+    //   www.example.          A 192.0.2.1, correctly signed, and no AAAA records
+    //   bad.example.          A 192.0.2.2, with a signature over 192.0.2.99
+    //   *.example.            A 192.0.2.4, correctly signed
+    //   spoofed.example.      A 192.0.2.5, signed by a made up root key
+    //   www.insecure.example. A 192.0.2.3, unsigned
+    //   hijacked.example.     A 192.0.2.6, signed by a made up key for hijacked.example., whose
+    //                         DS query is answered with NSEC3 records of the signed zone evil.
+    //                         claiming it's an unsigned delegation, one of them with too many
+    //                         iterations to check
+    //
+    // Returns the records along with trust anchors for the root and for example.
+    fn hierarchy() -> (Zone, [TrustAnchor; 2]) {
+        let root = ZoneKey::new(".");
+        let example = ZoneKey::new("example");
+        let a = |owner, last| record(owner, 300, RData::A([192, 0, 2, last].into()));
+        let mut zone = Zone::new();
+        let mut answer = |owner: &str, r#type, answers, authority| {
+            zone.insert((name(owner), r#type), (answers, authority));
+        };
+
+        answer(
+            ".",
+            RecordType::DNSKEY,
+            root.signed(vec![record(".", 3600, root.dnskey.clone())]),
+            vec![],
+        );
+        answer(
+            "example",
+            RecordType::DS,
+            root.signed(vec![record("example", 3600, example.ds())]),
+            vec![],
+        );
+        let dnskey = record("example", 3600, example.dnskey.clone());
+        answer(
+            "example",
+            RecordType::DNSKEY,
+            example.signed(vec![dnskey]),
+            vec![],
+        );
+        answer(
+            "www.example",
+            RecordType::A,
+            example.signed(vec![a("www.example", 1)]),
+            vec![],
+        );
+
+        let bad = vec![a("bad.example", 2), example.sign(&[a("bad.example", 99)])];
+        answer("bad.example", RecordType::A, bad, vec![]);
+
+        let nsec = RData::NSEC {
+            next: name("example"),
+            types: vec![RecordType::A, RecordType::RRSIG, RecordType::NSEC],
+        };
+        let nsec = example.signed(vec![record("www.example", 300, nsec)]);
+        answer("www.example", RecordType::AAAA, vec![], nsec);
+
+        // The answer for any.example. is synthesized from the wildcard, with its signature.
+        let mut wildcard = example.signed(vec![a("*.example", 4)]);
+        for record in &mut wildcard {
+            record.name = name("any.example");
+        }
+        answer("any.example", RecordType::A, wildcard, vec![]);
+
+        let forger = ZoneKey::new(".");
+        let spoofed = forger.signed(vec![a("spoofed.example", 5)]);
+        answer("spoofed.example", RecordType::A, spoofed, vec![]);
+
+        let nsec = RData::NSEC {
+            next: name("www.example"),
+            types: vec![RecordType::NS, RecordType::RRSIG, RecordType::NSEC],
+        };
+        let nsec = example.signed(vec![record("insecure.example", 300, nsec)]);
+        answer("insecure.example", RecordType::DS, vec![], nsec);
+        answer(
+            "www.insecure.example",
+            RecordType::A,
+            vec![a("www.insecure.example", 3)],
+            vec![],
+        );
+
+        let evil = ZoneKey::new("evil");
+        answer(
+            "evil",
+            RecordType::DS,
+            root.signed(vec![record("evil", 3600, evil.ds())]),
+            vec![],
+        );
+        answer(
+            "evil",
+            RecordType::DNSKEY,
+            evil.signed(vec![record("evil", 3600, evil.dnskey.clone())]),
+            vec![],
+        );
+        let hijacked = ZoneKey::new("hijacked.example");
+        answer(
+            "hijacked.example",
+            RecordType::A,
+            hijacked.signed(vec![a("hijacked.example", 6)]),
+            vec![],
+        );
+        let nsec3 = |iterations| {
+            let hash = nsec3_hash(&name("hijacked.example"), b"", iterations);
+            let owner = format!("{}.evil", base32hex(&hash));
+            let rdata = RData::NSEC3 {
+                hash_algorithm: 1,
+                flags: 0,
+                iterations,
+                salt: Vec::new(),
+                next_hashed: vec![0xff; 20],
+                types: vec![RecordType::NS],
+            };
+            evil.signed(vec![record(&owner, 300, rdata)])
+        };
+        let proofs = [nsec3(0), nsec3(MAX_NSEC3_ITERATIONS + 1)].concat();
+        answer("hijacked.example", RecordType::DS, vec![], proofs);
+
+        let anchors = [
+            TrustAnchor {
+                zone: Name::root(),
+                ds: root.ds(),
+            },
+            TrustAnchor {
+                zone: name("example"),
+                ds: example.ds(),
+            },
+        ];
+        (zone, anchors)
+    }
+
+    // Run a stand-in recursive resolver that answers from the zone, with an empty NOERROR response
+    // to any other question.
+    fn serve(zone: Zone) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok((n, from)) = socket.recv_from(&mut buf) {
+                let mut message = Message::try_from(&buf[..n]).unwrap();
+                let question = &message.questions[0];
+                let (answers, authority) = zone
+                    .get(&(question.name.clone(), question.r#type))
+                    .cloned()
+                    .unwrap_or_default();
+                message.header.flags.qr = true;
+                message.answers = answers;
+                message.authority = authority;
+                message.additional.clear();
+                socket.send_to(&message.encode().unwrap(), from).unwrap();
+            }
+        });
+        server
+    }
+
+    fn options() -> Options {
+        Options {
+            timeout: Duration::from_secs(1),
+            retries: 0,
+            ..Options::default()
+        }
+    }
+
+    fn status(
+        domain: &str,
+        r#type: RecordType,
+        server: SocketAddr,
+        anchors: &[TrustAnchor],
+    ) -> Status {
+        let validated = validate(domain, r#type, &[server], &options(), anchors).unwrap();
+        assert_eq!(validated.rrsets.len(), 1);
+        validated.rrsets[0].status.clone()
+    }
+
+    #[test]
+    fn validate_chain_of_trust() {
+        let (zone, [root, _]) = hierarchy();
+        let server = serve(zone);
+        let status = |domain| status(domain, RecordType::A, server, slice::from_ref(&root));
+        assert_eq!(status("www.example"), Status::Secure);
+        assert!(matches!(status("bad.example"), Status::Bogus(_)));
+        assert!(matches!(status("spoofed.example"), Status::Bogus(_)));
+        assert_eq!(status("www.insecure.example"), Status::Insecure);
+    }
+
+    #[test]
+    fn reject_proofs_from_other_zones() {
+        // The NSEC3 records are validly signed, but by evil., not by example., so they say
+        // nothing about hijacked.example.
+        let (zone, [root, _]) = hierarchy();
+        let server = serve(zone);
+        let Status::Bogus(reason) = status("hijacked.example", RecordType::A, server, &[root])
+        else {
+            panic!("hijacked record is not bogus");
+        };
+        assert!(reason.contains("not from example."), "{reason}");
+    }
+
+    #[test]
+    fn reject_signers_above_trust_anchor() {
+        // With only a trust anchor for example., the root isn't trusted, so the forged root
+        // signature must not make the record look merely unsigned.
+        let (zone, [_, example]) = hierarchy();
+        let server = serve(zone);
+        let status = |domain| status(domain, RecordType::A, server, slice::from_ref(&example));
+        assert_eq!(status("www.example"), Status::Secure);
+        let Status::Bogus(reason) = status("spoofed.example") else {
+            panic!("spoofed record is not bogus");
+        };
+        assert!(reason.contains("above the trust anchor"), "{reason}");
+    }
+
+    #[test]
+    fn unchecked_proofs_are_indeterminate() {
+        let (zone, [root, _]) = hierarchy();
+        let server = serve(zone);
+        let anchors = [root];
+        assert!(matches!(
+            status("www.example", RecordType::AAAA, server, &anchors),
+            Status::Indeterminate(_)
+        ));
+        assert!(matches!(
+            status("any.example", RecordType::A, server, &anchors),
+            Status::Indeterminate(_)
+        ));
+        // Neither passes validation.
+        for (domain, r#type) in [
+            ("www.example", RecordType::AAAA),
+            ("any.example", RecordType::A),
+        ] {
+            let validated = validate(domain, r#type, &[server], &options(), &anchors).unwrap();
+            assert!(validated.failed());
+        }
+        let validated = validate(
+            "www.example",
+            RecordType::A,
+            &[server],
+            &options(),
+            &anchors,
+        )
+        .unwrap();
+        assert!(!validated.failed());
+        // A negative response without any proof in a signed zone is bogus.
+        assert!(matches!(
+            status("www.example", RecordType::MX, server, &anchors),
+            Status::Bogus(_)
+        ));
+    }
+}
@@ -0,0 +1,390 @@
+use crate::tls::Tls;
+use anyhow::{anyhow, bail, Context, Error};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+// Media type of DNS messages sent over HTTP.
+const DNS_MESSAGE: &str = "application/dns-message";
+
+// Longest status, header or chunk size line we accept, and most header lines. Like the body,
+// these keep a server from making us buffer whatever it sends.
+const MAX_LINE_LENGTH: usize = 8192;
+const MAX_HEADERS: usize = 100;
+
+// Largest body we accept. It has to hold a DNS message, which can't be any bigger than this, and
+// a server shouldn't get to make us allocate whatever it claims to be sending.
+const MAX_BODY_SIZE: usize = u16::MAX as usize;
+
+// How the query is put in the HTTP request (RFC 8484 section 4.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    // The query is the body of a POST request.
+    Post,
+    // The query is base64url encoded into the dns parameter of a GET request, which is friendlier
+    // to HTTP caches.
+    Get,
+}
+
+// Settings for DNS-over-HTTPS (RFC 8484): queries are sent as HTTP/1.1 requests to an endpoint
+// such as https://dns.example/dns-query, over TLS.
+pub struct Doh {
+    host: String,
+    port: u16,
+    path: String,
+    pub method: Method,
+    tls: Tls,
+}
+
+impl Doh {
+    // Use an https:// endpoint URL with an optional port. The server's certificate must be valid
+    // for the host in the URL and signed by one of the roots trusted by tls.
+    pub fn new(endpoint: &str, mut tls: Tls) -> Result<Self, Error> {
+        let rest = endpoint
+            .strip_prefix("https://")
+            .ok_or(anyhow!("DoH endpoint {endpoint} is not an https:// URL"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (host, port.parse()?),
+            _ => (authority, 443),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            bail!("DoH endpoint {endpoint} has no host");
+        }
+        tls.server_name = Some(host.to_string());
+        Ok(Doh {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            method: Method::Post,
+            tls,
+        })
+    }
+
+    // Addresses of the endpoint's host, found with the system resolver.
+    pub fn servers(&self) -> Result<Vec<SocketAddr>, Error> {
+        let servers: Vec<_> = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .with_context(|| format!("could not resolve DoH endpoint {}", self.host))?
+            .collect();
+        if servers.is_empty() {
+            bail!("DoH endpoint {} has no addresses", self.host);
+        }
+        Ok(servers)
+    }
+
+    // Send an encoded query to one of the endpoint's addresses and return the encoded response.
+    pub(crate) fn exchange(
+        &self,
+        request: &[u8],
+        server: SocketAddr,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, Error> {
+        let host = match (self.host.contains(':'), self.port) {
+            (false, 443) => self.host.clone(),
+            (false, port) => format!("{}:{port}", self.host),
+            (true, 443) => format!("[{}]", self.host),
+            (true, port) => format!("[{}]:{port}", self.host),
+        };
+        let mut http = match self.method {
+            Method::Post => format!(
+                "POST {} HTTP/1.1\r\nContent-Type: {DNS_MESSAGE}\r\nContent-Length: {}\r\n",
+                self.path,
+                request.len()
+            ),
+            Method::Get => {
+                let separator = if self.path.contains('?') { '&' } else { '?' };
+                let dns = URL_SAFE_NO_PAD.encode(request);
+                format!("GET {}{separator}dns={dns} HTTP/1.1\r\n", self.path)
+            }
+        }
+        .into_bytes();
+        write!(
+            http,
+            "Host: {host}\r\nAccept: {DNS_MESSAGE}\r\nConnection: close\r\n\r\n"
+        )?;
+        if self.method == Method::Post {
+            http.extend(request);
+        }
+
+        let mut stream = self.tls.connect(server, timeout)?;
+        stream.write_all(&http)?;
+        stream.flush()?;
+        read_response(BufReader::new(stream))
+    }
+}
+
+// Read an HTTP/1.1 response and return its body, which must be a DNS message.
+fn read_response(mut r: impl BufRead) -> Result<Vec<u8>, Error> {
+    let mut line = String::new();
+    read_line(&mut r, &mut line)?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .ok_or(anyhow!("invalid HTTP status line {:?}", line.trim_end()))?
+        .to_string();
+    if status != "200" {
+        bail!("HTTP request failed: {}", line.trim_end());
+    }
+
+    let mut content_type = None;
+    let mut content_length = None;
+    let mut chunked = false;
+    let mut headers = 0;
+    loop {
+        if read_line(&mut r, &mut line)? == 0 {
+            bail!("HTTP response ended in the headers");
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        headers += 1;
+        if headers > MAX_HEADERS {
+            bail!("HTTP response has more than {MAX_HEADERS} headers");
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "content-type" => content_type = Some(value.to_string()),
+            "content-length" => content_length = Some(value.parse::<usize>()?),
+            "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+            _ => {}
+        }
+    }
+    // Parameters such as a charset may follow the media type, which is case-insensitive.
+    let media_type = content_type
+        .as_deref()
+        .and_then(|value| value.split(';').next())
+        .map(str::trim);
+    if !media_type.is_some_and(|media_type| media_type.eq_ignore_ascii_case(DNS_MESSAGE)) {
+        bail!("HTTP response has content type {content_type:?}, not {DNS_MESSAGE}");
+    }
+
+    let mut body = Vec::new();
+    if chunked {
+        // Each chunk is its size in hex on a line of its own, then the data and a line break. A
+        // chunk of size zero ends the body.
+        loop {
+            read_line(&mut r, &mut line)?;
+            let size = line.trim_end().split(';').next().unwrap_or("");
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|_| anyhow!("invalid chunk size {size:?}"))?;
+            if size == 0 {
+                break;
+            }
+            let start = body.len();
+            if size > MAX_BODY_SIZE - start {
+                bail!("HTTP response body is larger than {MAX_BODY_SIZE} bytes");
+            }
+            body.resize(start + size, 0);
+            r.read_exact(&mut body[start..])?;
+            r.read_exact(&mut [0; 2])?;
+        }
+    } else if let Some(length) = content_length {
+        if length > MAX_BODY_SIZE {
+            bail!("HTTP response body is larger than {MAX_BODY_SIZE} bytes");
+        }
+        body.resize(length, 0);
+        r.read_exact(&mut body)?;
+    } else {
+        r.take(MAX_BODY_SIZE as u64 + 1).read_to_end(&mut body)?;
+        if body.len() > MAX_BODY_SIZE {
+            bail!("HTTP response body is larger than {MAX_BODY_SIZE} bytes");
+        }
+    }
+    Ok(body)
+}
+
+// Read a line into line, replacing what was in it. Fails if the line is longer than
+// MAX_LINE_LENGTH. Returns the number of bytes read, which is zero at the end of the response.
+fn read_line(r: &mut impl BufRead, line: &mut String) -> Result<usize, Error> {
+    line.clear();
+    let n = r.take(MAX_LINE_LENGTH as u64).read_line(line)?;
+    if n == MAX_LINE_LENGTH && !line.ends_with('\n') {
+        bail!("HTTP response has a line longer than {MAX_LINE_LENGTH} bytes");
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::Query;
+    use crate::rdata::{RData, RecordType};
+    use crate::test_support::reply;
+    use crate::tls::test::server_config;
+    use crate::transport::{self, Options};
+    use quickcheck_macros::quickcheck;
+    use rustls::pki_types::CertificateDer;
+    use rustls::{ServerConnection, StreamOwned};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    // Run an HTTPS stand-in with a self-signed certificate for 127.0.0.1, which answers one query
+    // with an A record, in chunks if asked to. The request line is sent back on the channel.
+    fn serve(chunked: bool) -> (SocketAddr, CertificateDer<'static>, mpsc::Receiver<String>) {
+        let (config, certificate) = server_config(&["127.0.0.1"]);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap();
+        let (requests, received) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let connection = ServerConnection::new(config).unwrap();
+            let mut stream = BufReader::new(StreamOwned::new(connection, stream));
+            let mut request_line = String::new();
+            stream.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).unwrap();
+                if let Some(length) = line.strip_prefix("Content-Length: ") {
+                    content_length = length.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut query = vec![0; content_length];
+            stream.read_exact(&mut query).unwrap();
+            if let Some((_, dns)) = request_line.split_once("?dns=") {
+                let dns = dns.split_whitespace().next().unwrap();
+                query = URL_SAFE_NO_PAD.decode(dns).unwrap();
+            }
+            requests.send(request_line.trim_end().to_string()).unwrap();
+
+            let response = reply(&query, 0, &[RData::A([192, 0, 2, 1].into())]);
+            let stream = stream.get_mut();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: {DNS_MESSAGE}\r\n").unwrap();
+            if chunked {
+                let (first, second) = response.split_at(10);
+                write!(stream, "Transfer-Encoding: chunked\r\n\r\n").unwrap();
+                for chunk in [first, second] {
+                    write!(stream, "{:x}\r\n", chunk.len()).unwrap();
+                    stream.write_all(chunk).unwrap();
+                    write!(stream, "\r\n").unwrap();
+                }
+                write!(stream, "0\r\n\r\n").unwrap();
+            } else {
+                write!(stream, "Content-Length: {}\r\n\r\n", response.len()).unwrap();
+                stream.write_all(&response).unwrap();
+            }
+            stream.flush().unwrap();
+        });
+        (server, certificate, received)
+    }
+
+    fn lookup(method: Method, chunked: bool) -> String {
+        let (server, certificate, requests) = serve(chunked);
+        let tls = Tls::with_certificates(vec![certificate]).unwrap();
+        let endpoint = format!("https://127.0.0.1:{}/dns-query", server.port());
+        let mut doh = Doh::new(&endpoint, tls).unwrap();
+        doh.method = method;
+        let servers = doh.servers().unwrap();
+        let options = Options {
+            timeout: Duration::from_secs(1),
+            retries: 0,
+            doh: Some(doh),
+            ..Options::default()
+        };
+        let query = Query {
+            edns: None,
+            ..Query::new("example.com", RecordType::A)
+        };
+        let (response, _) = transport::exchange(&query, &servers, &options).unwrap();
+        assert_eq!(response.answers[0].rdata.to_string(), "192.0.2.1");
+        requests.recv().unwrap()
+    }
+
+    #[test]
+    fn query_with_post() {
+        assert_eq!(lookup(Method::Post, false), "POST /dns-query HTTP/1.1");
+    }
+
+    #[test]
+    fn query_with_get() {
+        let request = lookup(Method::Get, true);
+        assert!(request.starts_with("GET /dns-query?dns="), "{request}");
+        // The query is base64url encoded without padding.
+        assert!(!request.ends_with("= HTTP/1.1"), "{request}");
+    }
+
+    #[test]
+    fn parse_endpoints() {
+        let tls = || Tls::with_certificates(Vec::new()).unwrap();
+        let doh = Doh::new("https://dns.example/dns-query", tls()).unwrap();
+        assert_eq!((doh.host.as_str(), doh.port), ("dns.example", 443));
+        assert_eq!(doh.path, "/dns-query");
+        let doh = Doh::new("https://[::1]:8443", tls()).unwrap();
+        assert_eq!((doh.host.as_str(), doh.port), ("::1", 8443));
+        assert_eq!(doh.path, "/");
+        assert!(Doh::new("http://dns.example/dns-query", tls()).is_err());
+    }
+
+    #[test]
+    fn reject_oversized_bodies() {
+        let header = format!("HTTP/1.1 200 OK\r\nContent-Type: {DNS_MESSAGE}\r\n");
+        let response = format!("{header}Content-Length: 18446744073709551615\r\n\r\n");
+        assert!(read_response(response.as_bytes()).is_err());
+        let response = format!("{header}Transfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\n");
+        assert!(read_response(response.as_bytes()).is_err());
+        let response = format!("{header}Content-Length: 3\r\n\r\nabc");
+        assert_eq!(read_response(response.as_bytes()).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn reject_long_lines() {
+        let response = format!("HTTP/1.1 200 OK\r\nX-Padding: {}\r\n", "a".repeat(100_000));
+        let err = read_response(response.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("longer than"), "{err}");
+
+        // A response with the given number of headers, padded out after the content type.
+        let headers = |count| {
+            let padding = "X-Padding: a\r\n".repeat(count - 2);
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {DNS_MESSAGE}\r\n{padding}\
+                 Content-Length: 3\r\n\r\nabc"
+            )
+        };
+        assert_eq!(
+            read_response(headers(MAX_HEADERS).as_bytes()).unwrap(),
+            b"abc"
+        );
+        let err = read_response(headers(MAX_HEADERS + 1).as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("more than 100 headers"), "{err}");
+    }
+
+    #[test]
+    fn accept_media_type_parameters() {
+        let response =
+            "HTTP/1.1 200 OK\r\nContent-Type: Application/DNS-Message; charset=utf-8\r\n\
+                        Content-Length: 3\r\n\r\nabc";
+        assert_eq!(read_response(response.as_bytes()).unwrap(), b"abc");
+        let response = "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 3\r\n\r\nabc";
+        assert!(read_response(response.as_bytes()).is_err());
+    }
+
+    // Whatever the server sends, we get a body or an error.
+    #[quickcheck]
+    fn read_arbitrary_responses(body: Vec<u8>, chunked: bool) -> bool {
+        let encoding = match chunked {
+            true => "Transfer-Encoding: chunked",
+            false => "X-Padding: none",
+        };
+        let mut response =
+            format!("HTTP/1.1 200 OK\r\nContent-Type: {DNS_MESSAGE}\r\n{encoding}\r\n\r\n")
+                .into_bytes();
+        response.extend(body);
+        let _ = read_response(response.as_slice());
+        true
+    }
+}
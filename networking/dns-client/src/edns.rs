@@ -0,0 +1,330 @@
+use crate::message::Record;
+use crate::output::{hex, serialize_hex};
+use crate::rdata::{RData, RecordType};
+use anyhow::{anyhow, bail, Error};
+use serde::Serialize;
+use std::fmt::{self, Display};
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// EDNS(0) parameters carried in an OPT pseudo-record (RFC 6891). The OPT record reuses the fixed
+// resource record fields for its own purposes:
+//
+// +------------+--------------+------------------------------+
+// | Field Name | Field Type   | Description                  |
+// +------------+--------------+------------------------------+
+// | NAME       | domain name  | MUST be 0 (root domain)      |
+// | TYPE       | u_int16_t    | OPT (41)                     |
+// | CLASS      | u_int16_t    | requestor's UDP payload size |
+// | TTL        | u_int32_t    | extended RCODE and flags     |
+// | RDLEN      | u_int16_t    | length of all RDATA          |
+// | RDATA      | octet stream | {attribute,value} pairs      |
+// +------------+--------------+------------------------------+
+//
+// The TTL field is split into:
+//
+// +0 (MSB)                            +1 (LSB)
+// +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+// |         EXTENDED-RCODE        |            VERSION            |
+// +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+// | DO|                           Z                               |
+// +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Edns {
+    pub payload_size: u16,
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+// Advertise a payload size that avoids IP fragmentation on common links (DNS Flag Day 2020).
+impl Default for Edns {
+    fn default() -> Self {
+        Edns {
+            payload_size: 1232,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }
+    }
+}
+
+impl Edns {
+    // Write the OPT pseudo-record for the additional section.
+    pub fn encode(&self, mut w: impl Write) -> Result<usize, Error> {
+        let mut rdata = Vec::new();
+        for option in &self.options {
+            option.encode(&mut rdata)?;
+        }
+        let ttl = (self.extended_rcode as u32) << 24
+            | (self.version as u32) << 16
+            | if self.dnssec_ok { 0x8000 } else { 0 };
+        w.write_all(&[0])?; // Root name
+        w.write_all(&u16::from(RecordType::OPT).to_be_bytes())?;
+        w.write_all(&self.payload_size.to_be_bytes())?;
+        w.write_all(&ttl.to_be_bytes())?;
+        w.write_all(&u16::try_from(rdata.len())?.to_be_bytes())?;
+        w.write_all(&rdata)?;
+        Ok(11 + rdata.len())
+    }
+
+    // Extract the EDNS parameters from a parsed OPT record.
+    pub fn from_record(record: &Record) -> Option<Self> {
+        let RData::OPT(options) = &record.rdata else {
+            return None;
+        };
+        Some(Edns {
+            payload_size: record.class,
+            extended_rcode: (record.ttl >> 24) as u8,
+            version: (record.ttl >> 16) as u8,
+            dnssec_ok: record.ttl & 0x8000 != 0,
+            options: options.clone(),
+        })
+    }
+}
+
+// Display the parameters like dig's OPT pseudosection.
+impl Display for Edns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "; EDNS: version: {}, flags:", self.version)?;
+        if self.dnssec_ok {
+            write!(f, " do")?;
+        }
+        write!(f, "; udp: {}", self.payload_size)?;
+        for option in &self.options {
+            write!(f, "\n; {option}")?;
+        }
+        Ok(())
+    }
+}
+
+// A single EDNS option from the OPT RDATA:
+//
+// +0 (MSB)                            +1 (LSB)
+// +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+// |                          OPTION-CODE                          |
+// +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+// |                         OPTION-LENGTH                         |
+// +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+// /                          OPTION-DATA                          /
+// +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "option", rename_all = "kebab-case")]
+pub enum EdnsOption {
+    // Client subnet (RFC 7871). Only the first source_prefix bits of the address are sent.
+    ClientSubnet {
+        source_prefix: u8,
+        scope_prefix: u8,
+        address: IpAddr,
+    },
+    // Cookie (RFC 7873). The client cookie is 8 bytes, and the server cookie is 8 to 32 bytes or
+    // empty when we don't know it yet.
+    Cookie {
+        #[serde(serialize_with = "serialize_hex")]
+        client: Vec<u8>,
+        #[serde(serialize_with = "serialize_hex")]
+        server: Vec<u8>,
+    },
+    Unknown {
+        code: u16,
+        #[serde(serialize_with = "serialize_hex")]
+        data: Vec<u8>,
+    },
+}
+
+const CLIENT_SUBNET: u16 = 8;
+const COOKIE: u16 = 10;
+
+impl EdnsOption {
+    // A client cookie with random contents.
+    pub fn new_cookie() -> Self {
+        EdnsOption::Cookie {
+            client: rand::random::<[u8; 8]>().to_vec(),
+            server: Vec::new(),
+        }
+    }
+
+    // Parse a client subnet in ADDRESS/PREFIX form, such as 192.0.2.0/24. Without a prefix the
+    // whole address is sent.
+    pub fn parse_client_subnet(s: &str) -> Result<Self, Error> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address.parse::<IpAddr>()?, Some(prefix.parse::<u8>()?)),
+            None => (s.parse::<IpAddr>()?, None),
+        };
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+        let source_prefix = prefix.unwrap_or(max_prefix);
+        if source_prefix > max_prefix {
+            bail!("prefix length {source_prefix} is too long for {address}");
+        }
+        Ok(EdnsOption::ClientSubnet {
+            source_prefix,
+            scope_prefix: 0,
+            address,
+        })
+    }
+
+    pub(crate) fn encode(&self, mut w: impl Write) -> Result<(), Error> {
+        let (code, data) = match self {
+            EdnsOption::ClientSubnet {
+                source_prefix,
+                scope_prefix,
+                address,
+            } => {
+                //                 +0 (MSB)                            +1 (LSB)
+                // +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+                // |                            FAMILY                             |
+                // +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+                // |     SOURCE PREFIX-LENGTH      |     SCOPE PREFIX-LENGTH       |
+                // +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+                // |                           ADDRESS...                          /
+                // +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+                let (family, octets): (u16, Vec<u8>) = match address {
+                    IpAddr::V4(addr) => (1, addr.octets().to_vec()),
+                    IpAddr::V6(addr) => (2, addr.octets().to_vec()),
+                };
+                let mut data = family.to_be_bytes().to_vec();
+                data.extend([*source_prefix, *scope_prefix]);
+                data.extend(truncate_address(&octets, *source_prefix));
+                (CLIENT_SUBNET, data)
+            }
+            EdnsOption::Cookie { client, server } => (COOKIE, [&client[..], server].concat()),
+            EdnsOption::Unknown { code, data } => (*code, data.clone()),
+        };
+        w.write_all(&code.to_be_bytes())?;
+        w.write_all(&u16::try_from(data.len())?.to_be_bytes())?;
+        w.write_all(&data)?;
+        Ok(())
+    }
+
+    // Decode an option. A malformed option is kept as Unknown rather than failing the whole
+    // message, as a server sending one bad option shouldn't make its answer unusable.
+    pub(crate) fn decode(code: u16, data: &[u8]) -> Self {
+        Self::decode_known(code, data).unwrap_or_else(|_| EdnsOption::Unknown {
+            code,
+            data: data.to_vec(),
+        })
+    }
+
+    fn decode_known(code: u16, data: &[u8]) -> Result<Self, Error> {
+        Ok(match code {
+            CLIENT_SUBNET => {
+                let [f0, f1, source_prefix, scope_prefix, address @ ..] = data else {
+                    bail!("client subnet option is too short");
+                };
+                let address = match u16::from_be_bytes([*f0, *f1]) {
+                    1 => IpAddr::V4(Ipv4Addr::from(pad_address::<4>(address)?)),
+                    2 => IpAddr::V6(Ipv6Addr::from(pad_address::<16>(address)?)),
+                    family => bail!("unknown client subnet address family {family}"),
+                };
+                EdnsOption::ClientSubnet {
+                    source_prefix: *source_prefix,
+                    scope_prefix: *scope_prefix,
+                    address,
+                }
+            }
+            COOKIE => {
+                if data.len() != 8 && !(16..=40).contains(&data.len()) {
+                    bail!("invalid cookie length {}", data.len());
+                }
+                EdnsOption::Cookie {
+                    client: data[..8].to_vec(),
+                    server: data[8..].to_vec(),
+                }
+            }
+            code => EdnsOption::Unknown {
+                code,
+                data: data.to_vec(),
+            },
+        })
+    }
+}
+
+impl Display for EdnsOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdnsOption::ClientSubnet {
+                source_prefix,
+                scope_prefix,
+                address,
+            } => write!(f, "CLIENT-SUBNET: {address}/{source_prefix}/{scope_prefix}"),
+            EdnsOption::Cookie { client, server } => {
+                write!(f, "COOKIE: {}", hex(client))?;
+                if !server.is_empty() {
+                    write!(f, " {}", hex(server))?;
+                }
+                Ok(())
+            }
+            EdnsOption::Unknown { code, data } => write!(f, "OPT{code}: {}", hex(data)),
+        }
+    }
+}
+
+// Keep only the bytes covering the first prefix bits, with any trailing bits cleared.
+fn truncate_address(octets: &[u8], prefix: u8) -> Vec<u8> {
+    let prefix = (prefix as usize).min(octets.len() * 8);
+    let mut truncated = octets[..prefix.div_ceil(8)].to_vec();
+    if !prefix.is_multiple_of(8) {
+        if let Some(last) = truncated.last_mut() {
+            *last &= 0xff << (8 - prefix % 8);
+        }
+    }
+    truncated
+}
+
+// Pad a truncated address back out to its full length with zeros.
+fn pad_address<const N: usize>(address: &[u8]) -> Result<[u8; N], Error> {
+    let mut octets = [0; N];
+    octets
+        .get_mut(..address.len())
+        .ok_or(anyhow!("client subnet address is too long"))?
+        .copy_from_slice(address);
+    Ok(octets)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::Message;
+
+    #[test]
+    fn client_subnet_round_trip() {
+        let option = EdnsOption::parse_client_subnet("192.0.2.77/20").unwrap();
+        let mut buf = Vec::new();
+        option.encode(&mut buf).unwrap();
+        // Code 8, length 7, family 1, prefixes 20/0, then 3 address bytes with bits cleared.
+        assert_eq!(buf, [0, 8, 0, 7, 0, 1, 20, 0, 192, 0, 0]);
+        let decoded = EdnsOption::decode(CLIENT_SUBNET, &buf[4..]);
+        assert_eq!(decoded.to_string(), "CLIENT-SUBNET: 192.0.0.0/20/0");
+        assert!(EdnsOption::parse_client_subnet("::1/129").is_err());
+    }
+
+    #[test]
+    fn parse_opt_record() {
+        // Response header with one additional record and an extended RCODE of BADVERS (16).
+        let mut msg = vec![0, 1, 0x80, 0x00, 0, 0, 0, 0, 0, 0, 0, 1];
+        let edns = Edns {
+            payload_size: 4096,
+            extended_rcode: 1,
+            dnssec_ok: true,
+            options: vec![EdnsOption::Cookie {
+                client: vec![1; 8],
+                server: vec![2; 16],
+            }],
+            ..Edns::default()
+        };
+        edns.encode(&mut msg).unwrap();
+        let msg = Message::try_from(msg.as_slice()).unwrap();
+        assert_eq!(msg.edns(), Some(edns));
+        assert_eq!(msg.rcode(), 16);
+    }
+
+    #[test]
+    fn keep_malformed_options() {
+        let option = EdnsOption::decode(COOKIE, &[1; 5]);
+        assert_eq!(option.to_string(), "OPT10: 0101010101");
+        let option = EdnsOption::decode(CLIENT_SUBNET, &[0, 3, 0, 0]);
+        assert!(matches!(option, EdnsOption::Unknown { code: 8, .. }));
+    }
+}
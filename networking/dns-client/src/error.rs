@@ -0,0 +1,79 @@
+use std::fmt::{self, Display};
+
+// A response whose RCODE indicates the query failed.
+//
+// RCODE  Name      Description
+// -----  --------  ---------------------------------------------------------------------------
+// 1      FORMERR   The name server was unable to interpret the query.
+// 2      SERVFAIL  The name server was unable to process the query due to a problem with it.
+// 3      NXDOMAIN  The domain name referenced in the query does not exist.
+// 4      NOTIMP    The name server does not support the requested kind of query.
+// 5      REFUSED   The name server refuses to perform the operation for policy reasons.
+//
+// UPDATE adds more codes for prerequisites that don't hold (RFC 2136 section 2.2), which are
+// kept as Other:
+//
+// 6      YXDOMAIN  A name exists that should not.
+// 7      YXRRSET   An RRset exists that should not.
+// 8      NXRRSET   An RRset that should exist does not.
+// 9      NOTAUTH   The server is not authoritative for the zone, or the signature is not valid.
+// 10     NOTZONE   A name in the update is outside the zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseError {
+    FormErr,
+    ServFail,
+    NXDomain,
+    NotImp,
+    Refused,
+    Other(u16),
+}
+
+impl ResponseError {
+    // Process exit code for the CLI, so scripts can tell the failures apart. General errors
+    // exit with 1.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ResponseError::FormErr => 11,
+            ResponseError::ServFail => 12,
+            ResponseError::NXDomain => 13,
+            ResponseError::NotImp => 14,
+            ResponseError::Refused => 15,
+            ResponseError::Other(_) => 10,
+        }
+    }
+}
+
+impl From<u16> for ResponseError {
+    fn from(rcode: u16) -> Self {
+        match rcode {
+            1 => ResponseError::FormErr,
+            2 => ResponseError::ServFail,
+            3 => ResponseError::NXDomain,
+            4 => ResponseError::NotImp,
+            5 => ResponseError::Refused,
+            rcode => ResponseError::Other(rcode),
+        }
+    }
+}
+
+impl Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseError::FormErr => write!(f, "FORMERR: the server could not parse the query"),
+            ResponseError::ServFail => {
+                write!(f, "SERVFAIL: the server failed to process the query")
+            }
+            ResponseError::NXDomain => write!(f, "NXDOMAIN: the domain does not exist"),
+            ResponseError::NotImp => write!(f, "NOTIMP: the server does not support the query"),
+            ResponseError::Refused => write!(f, "REFUSED: the server refused the query"),
+            ResponseError::Other(6) => write!(f, "YXDOMAIN: a name exists that should not"),
+            ResponseError::Other(7) => write!(f, "YXRRSET: an RRset exists that should not"),
+            ResponseError::Other(8) => write!(f, "NXRRSET: an RRset that should exist does not"),
+            ResponseError::Other(9) => write!(f, "NOTAUTH: the server refused to update the zone"),
+            ResponseError::Other(10) => write!(f, "NOTZONE: a name is outside the zone"),
+            ResponseError::Other(rcode) => write!(f, "RCODE{rcode}: the query failed"),
+        }
+    }
+}
+
+impl std::error::Error for ResponseError {}
@@ -0,0 +1,309 @@
+use crate::cache::{Cache, Cached, Stats};
+use crate::edns::Edns;
+use crate::error::ResponseError;
+use crate::message::Message;
+use crate::query::Query;
+use crate::server::Handler;
+use crate::transport::{self, Options};
+use std::net::SocketAddr;
+
+// RCODEs the forwarder answers with itself.
+const SERVFAIL: u8 = 2;
+const NXDOMAIN: u8 = 3;
+const REFUSED: u8 = 5;
+
+// A caching forwarder: it passes queries on to upstream recursive resolvers and keeps their
+// answers for as long as the TTLs allow, answering repeated questions from the cache. Run it with
+// server::run to give local clients a resolver.
+//
+// Cached answers only keep the answer records, and negative results their authority section, so
+// responses served from the cache have no additional records. Whether the upstream resolver
+// validated the answer (the AD bit) is kept with it.
+//
+// Answers fetched with the DO bit carry RRSIGs that answers fetched without it lack, so the two
+// are cached apart. Answers fetched with the CD bit haven't been validated upstream and may be
+// bogus, so they aren't cached at all, and queries with it always go upstream.
+pub struct Forwarder {
+    upstream: Vec<SocketAddr>,
+    pub options: Options,
+    cache: Cache,
+    dnssec_cache: Cache,
+}
+
+impl Forwarder {
+    pub fn new(upstream: Vec<SocketAddr>) -> Self {
+        Forwarder {
+            upstream,
+            options: Options::default(),
+            cache: Cache::default(),
+            dnssec_cache: Cache::default(),
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        let (plain, dnssec) = (self.cache.stats(), self.dnssec_cache.stats());
+        Stats {
+            hits: plain.hits + dnssec.hits,
+            misses: plain.misses + dnssec.misses,
+        }
+    }
+}
+
+impl Handler for Forwarder {
+    fn answer(&self, query: &Message, response: &mut Message) {
+        let question = &response.questions[0];
+        response.header.flags.ra = true;
+        if question.class != 1 {
+            response.header.flags.rcode = REFUSED;
+            return;
+        }
+        let dnssec_ok = query.edns().is_some_and(|edns| edns.dnssec_ok);
+        let checking_disabled = query.header.flags.cd;
+        let cache = match dnssec_ok {
+            true => &self.dnssec_cache,
+            false => &self.cache,
+        };
+        let cached = match checking_disabled {
+            true => None,
+            false => cache.lookup(&question.name, question.r#type, question.class),
+        };
+        match cached {
+            Some(Cached {
+                result: Ok(answers),
+                authority,
+                authenticated,
+            }) => {
+                response.header.flags.ad = authenticated;
+                response.answers = answers;
+                response.authority = authority;
+                return;
+            }
+            Some(Cached {
+                result: Err(ResponseError::NXDomain),
+                authority,
+                authenticated,
+            }) => {
+                response.header.flags.ad = authenticated;
+                response.header.flags.rcode = NXDOMAIN;
+                response.authority = authority;
+                return;
+            }
+            _ => {}
+        }
+
+        // Ask for DNSSEC records upstream only if the client did.
+        let upstream_query = Query {
+            edns: Some(Edns {
+                dnssec_ok,
+                ..Edns::default()
+            }),
+            checking_disabled,
+            ..Query::new(question.name.to_string(), question.r#type)
+        };
+        let Ok((upstream, _)) = transport::exchange(&upstream_query, &self.upstream, &self.options)
+        else {
+            response.header.flags.rcode = SERVFAIL;
+            return;
+        };
+        if !checking_disabled {
+            cache.insert(&upstream);
+        }
+        response.header.flags.rcode = upstream.header.flags.rcode;
+        response.header.flags.ad = upstream.header.flags.ad;
+        response.answers = upstream.answers;
+        response.authority = upstream.authority;
+        // The upstream OPT record carries the extended RCODE, and server::respond replaces the
+        // rest of it with our own.
+        response.additional = upstream.additional;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::name::Name;
+    use crate::rdata::{RData, RecordType};
+    use crate::server;
+    use crate::test_support::{reply, response};
+    use crate::zone::parse_record;
+    use std::net::{TcpListener, UdpSocket};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    // Run an upstream stand-in that answers every query with an A record with a TTL of 60, and
+    // counts the queries it gets. Names under missing.example.com don't exist, answers for
+    // secure.example.com are authenticated, and queries for badcookie.example.com fail with
+    // BADCOOKIE, an extended RCODE.
+    fn upstream() -> (SocketAddr, Arc<AtomicUsize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok((n, from)) = socket.recv_from(&mut buf) {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let query = Message::try_from(&buf[..n]).unwrap();
+                let missing = "missing.example.com".parse().unwrap();
+                let name = query.questions[0].name.to_string();
+                let response = if query.questions[0].name.is_subdomain_of(&missing) {
+                    let mut response = response(&buf[..n], 3);
+                    let soa = "example.com. 300 IN SOA ns hostmaster 1 7200 3600 1209600 60";
+                    response
+                        .authority
+                        .push(parse_record(soa, &Name::root(), None).unwrap());
+                    response
+                } else if name == "badcookie.example.com." {
+                    // BADCOOKIE is 23: 7 in the header and 1 in the OPT record.
+                    let mut response = response(&buf[..n], 7);
+                    let edns = Edns {
+                        extended_rcode: 1,
+                        ..Edns::default()
+                    };
+                    response.additional.push(server::opt_record(&edns));
+                    response
+                } else {
+                    let answers = [RData::A([192, 0, 2, 1].into())];
+                    let mut response =
+                        Message::try_from(reply(&buf[..n], 0, &answers).as_slice()).unwrap();
+                    response.header.flags.ad = name == "secure.example.com.";
+                    response
+                };
+                socket.send_to(&response.encode().unwrap(), from).unwrap();
+            }
+        });
+        (addr, count)
+    }
+
+    fn options() -> Options {
+        Options {
+            timeout: Duration::from_millis(500),
+            retries: 0,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn answer_from_cache() {
+        let (upstream, count) = upstream();
+        let mut forwarder = Forwarder::new(vec![upstream]);
+        forwarder.options = options();
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let local = udp.local_addr().unwrap();
+        thread::spawn(move || server::run(forwarder, udp, tcp));
+
+        for _ in 0..2 {
+            let query = Query::new("example.com", RecordType::A);
+            let (response, _) = transport::exchange(&query, &[local], &options()).unwrap();
+            assert!(response.header.flags.ra);
+            assert_eq!(response.answers[0].rdata.to_string(), "192.0.2.1");
+            assert!(response.answers[0].ttl <= 60);
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    // Send a query straight to the forwarder, with the DO and CD bits as given.
+    fn ask(forwarder: &Forwarder, name: &str, dnssec_ok: bool, checking_disabled: bool) -> Message {
+        let query = Query {
+            edns: Some(Edns {
+                dnssec_ok,
+                ..Edns::default()
+            }),
+            checking_disabled,
+            ..Query::new(name, RecordType::A)
+        };
+        let mut request = Vec::new();
+        query.encode(&mut request).unwrap();
+        let response = server::respond(forwarder, &request, true).unwrap();
+        Message::try_from(response.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn cache_by_dnssec_bits() {
+        let (upstream, count) = upstream();
+        let mut forwarder = Forwarder::new(vec![upstream]);
+        forwarder.options = options();
+        let queries = || count.load(Ordering::SeqCst);
+
+        ask(&forwarder, "example.com", false, false);
+        ask(&forwarder, "example.com", false, false);
+        assert_eq!(queries(), 1);
+        // An answer without DNSSEC records won't do for a client that asked for them.
+        ask(&forwarder, "example.com", true, false);
+        ask(&forwarder, "example.com", true, false);
+        assert_eq!(queries(), 2);
+        // Answers that weren't validated upstream are never cached or served from the cache.
+        ask(&forwarder, "www.example.com", false, true);
+        ask(&forwarder, "www.example.com", false, true);
+        assert_eq!(queries(), 4);
+        ask(&forwarder, "www.example.com", false, false);
+        assert_eq!(queries(), 5);
+    }
+
+    #[test]
+    fn cache_nxdomain_with_soa() {
+        let (upstream, count) = upstream();
+        let mut forwarder = Forwarder::new(vec![upstream]);
+        forwarder.options = options();
+        for cached in [false, true] {
+            let response = ask(&forwarder, "missing.example.com", false, false);
+            assert_eq!(response.header.flags.rcode, NXDOMAIN);
+            assert_eq!(response.authority[0].r#type, RecordType::SOA);
+            // From the cache, the SOA's TTL is capped by its MINIMUM field.
+            assert_eq!(response.authority[0].ttl <= 60, cached);
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn keep_authenticated_bit() {
+        let (upstream, count) = upstream();
+        let mut forwarder = Forwarder::new(vec![upstream]);
+        forwarder.options = options();
+        for name in ["secure.example.com", "example.com"] {
+            for _ in 0..2 {
+                let response = ask(&forwarder, name, true, false);
+                assert_eq!(response.header.flags.ad, name == "secure.example.com");
+            }
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn pass_on_extended_rcodes() {
+        let (upstream, _) = upstream();
+        let mut forwarder = Forwarder::new(vec![upstream]);
+        forwarder.options = options();
+        let response = ask(&forwarder, "badcookie.example.com", false, false);
+        assert_eq!(response.rcode(), 23);
+
+        // Without EDNS, the client can only be told that the query failed.
+        let query = Query {
+            edns: None,
+            ..Query::new("badcookie.example.com", RecordType::A)
+        };
+        let mut request = Vec::new();
+        query.encode(&mut request).unwrap();
+        let response = server::respond(&forwarder, &request, true).unwrap();
+        let response = Message::try_from(response.as_slice()).unwrap();
+        assert_eq!(response.rcode(), SERVFAIL as u16);
+    }
+
+    #[test]
+    fn fail_without_upstream() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut forwarder = Forwarder::new(vec![silent.local_addr().unwrap()]);
+        forwarder.options = options();
+        let mut request = Vec::new();
+        Query::new("example.com", RecordType::A)
+            .encode(&mut request)
+            .unwrap();
+        let response = server::respond(&forwarder, &request, true).unwrap();
+        let response = Message::try_from(response.as_slice()).unwrap();
+        assert_eq!(response.header.flags.rcode, SERVFAIL);
+        assert_eq!(forwarder.stats(), Stats { hits: 0, misses: 1 });
+    }
+}
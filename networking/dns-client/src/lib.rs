@@ -0,0 +1,34 @@
+// A DNS client library: a wire format encoder and parser, UDP and TCP transports, and a stub
+// resolver built on top of them. The dns-client binary is a thin command line wrapper around it.
+
+pub mod async_resolver;
+pub mod batch;
+pub mod cache;
+pub mod config;
+pub mod dnssec;
+pub mod doh;
+pub mod edns;
+pub mod error;
+pub mod forwarder;
+pub mod message;
+pub mod name;
+pub mod output;
+pub mod query;
+pub mod rdata;
+pub mod resolver;
+pub mod server;
+#[cfg(test)]
+mod test_support;
+pub mod tls;
+pub mod trace;
+pub mod transport;
+pub mod tsig;
+pub mod update;
+pub mod xfr;
+pub mod zone;
+
+pub use async_resolver::AsyncResolver;
+pub use cache::Cache;
+pub use error::ResponseError;
+pub use rdata::{RData, RecordType};
+pub use resolver::{Resolver, Response};
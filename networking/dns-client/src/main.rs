@@ -1,231 +1,573 @@
-use anyhow::{anyhow, Error};
-use std::fmt::Display;
-use std::{
-    env,
-    io::{Cursor, Write},
-    net::UdpSocket,
-};
-
-fn main() -> Result<(), Error> {
-    // Get the domain from the first CLI argument.
-    let domain = env::args()
-        .nth(1)
-        .ok_or(anyhow!("please provide a domain"))?;
-
-    // Create a socket to send and receive UDP packets.
-    let socket = UdpSocket::bind(("0.0.0.0", 1337))?;
-    // We are only querying 1.1 (equivalent to 1.0.0.1) which is Cloudflare's nameserver.
-    socket.connect(("1.1", 53))?;
-
-    // Create a buffer to read/write.
-    let mut buf = Cursor::new([0; 2048]);
-
-    // Encode a query for miccah.io into the buffer and send it to the nameserver.
-    let n = Query(&domain).encode(&mut buf)?;
-    socket.send(&buf.get_ref()[..n])?;
-
-    // Read and parse a response from the nameserver.
-    let n = socket.recv(buf.get_mut())?;
-    let answers = Answers::try_from(&buf.get_ref()[..n])?;
-
-    // Display the answers.
-    println!("Response for {domain}");
-    for answer in answers {
-        println!("{}", answer);
+use anyhow::{anyhow, bail, Context, Error};
+use dns_client::batch::{self, Order};
+use dns_client::dnssec::{self, TrustAnchor};
+use dns_client::doh::{Doh, Method};
+use dns_client::edns::{Edns, EdnsOption};
+use dns_client::forwarder::Forwarder;
+use dns_client::name::Name;
+use dns_client::output::{Json, Presentation, Verbose};
+use dns_client::server::{self, Server};
+use dns_client::tls::{Tls, DOT_PORT};
+use dns_client::transport::Options;
+use dns_client::tsig::Key;
+use dns_client::update::{Change, Prerequisite, Update};
+use dns_client::xfr::{self, Section, Transfer};
+use dns_client::zone::Zone;
+use dns_client::{config, trace, AsyncResolver, RecordType, Resolver, Response, ResponseError};
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr, TcpListener, UdpSocket};
+use std::sync::Arc;
+use std::{env, fs, process::ExitCode, time::Duration};
+
+// Exit with a distinct code for each kind of failed response (see ResponseError::exit_code), or 1
+// for any other error.
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            match err.downcast_ref::<ResponseError>() {
+                Some(err) => ExitCode::from(err.exit_code()),
+                None => ExitCode::FAILURE,
+            }
+        }
     }
-    Ok(())
 }
 
-// Simplified DNS query for a provided domain's A record.
-struct Query<T: AsRef<str>>(T);
+fn run() -> Result<(), Error> {
+    let Args {
+        domain,
+        r#type,
+        servers,
+        options,
+        edns,
+        output,
+        trace,
+        batch,
+        anchors,
+        serve,
+        forward,
+        transfer,
+        update,
+    } = Args::parse(env::args().skip(1))?;
 
-impl<T: AsRef<str>> Query<T> {
-    // Encode the query as bytes.
-    fn encode(&self, mut w: impl Write) -> Result<usize, Error> {
-        Ok(Self::encode_header(&mut w)? + self.encode_question(&mut w)?)
+    if let Some(serve) = serve {
+        return run_server(serve);
+    }
+    if let Some(listen) = forward {
+        return run_forwarder(listen, servers, options);
+    }
+    if let Some(update) = update {
+        return run_update(update, servers, options);
     }
 
-    // Write a hard-coded header as bytes. The header indicates we are only asking one question
-    // with recursion desired set.
-    fn encode_header(mut w: impl Write) -> Result<usize, Error> {
-        //                                 1  1  1  1  1  1
-        //   0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                      ID                       |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |QR|   Opcode  |AA|TC|RD|RA|   Z    |   RCODE   |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                    QDCOUNT                    |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                    ANCOUNT                    |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                    NSCOUNT                    |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                    ARCOUNT                    |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        w.write_all(&[
-            5, 57, // ID
-            1, 0, // Header flags: RD (recursion desired)
-            0, 1, // Question count: 1
-            0, 0, // Answer count: 0
-            0, 0, // Name server count: 0
-            0, 0, // Additional record count: 0
-        ])?;
-        Ok(12)
+    if let Some(batch) = batch {
+        return run_batch(batch, r#type, servers, options, edns);
+    }
+    let domain = domain.ok_or(anyhow!("please provide a domain"))?;
+    if let Some(transfer) = transfer {
+        return run_transfer(&domain, transfer, servers, options);
     }
 
-    // Encode the question as bytes, assuming an A record and IN class.
-    fn encode_question(&self, mut w: impl Write) -> Result<usize, Error> {
-        //                                 1  1  1  1  1  1
-        //   0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                                               |
-        // /                     QNAME                     /
-        // /                                               /
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                     QTYPE                     |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                     QCLASS                    |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        let domain = self.0.as_ref();
-        let mut nbytes = 0;
-        for octet in domain.split('.') {
-            let octet_length = u8::try_from(octet.len())?;
-            let octet_bytes = octet.as_bytes();
-            w.write_all(&[octet_length])?;
-            w.write_all(octet_bytes)?;
-            nbytes += octet_bytes.len() + 1;
-        }
-        w.write_all(&[0])?;
-        nbytes += 1;
-        w.write_all(&[
-            0, 1, // Question type: A
-            0, 1, // Question class: IN (internet)
-        ])?;
-        nbytes += 4;
-        Ok(nbytes)
+    // Walk down from the root servers (or the given servers) instead of asking a recursive
+    // resolver. Each referral is printed as it is received.
+    if trace {
+        let roots = match servers {
+            servers if servers.is_empty() => trace::root_hints(),
+            servers => servers,
+        };
+        trace::trace(&domain, r#type, &roots, &options, |hop| println!("{hop}\n"))?;
+        return Ok(());
     }
-}
 
-#[derive(Debug)]
-struct Answers(Vec<Answer>);
+    // Rebuild the chain of trust from the trust anchors down to the answer, then print the status
+    // of each RRset after the records.
+    if let Some(anchors) = anchors {
+        let servers = match servers {
+            servers if servers.is_empty() => config::system_servers()?,
+            servers => servers,
+        };
+        let validated = dnssec::validate(&domain, r#type, &servers, &options, &anchors)?;
+        let Response {
+            message: response,
+            server,
+            ..
+        } = &validated.response;
+        println!("{type} response for {domain} from {server}");
+        for answer in &response.answers {
+            println!("{}", answer);
+        }
+        for rrset in &validated.rrsets {
+            println!("; {} {}: {}", rrset.name, rrset.r#type, rrset.status);
+        }
+        // An NXDOMAIN is only reported once validation has vouched for it.
+        if validated.failed() {
+            bail!("DNSSEC validation failed");
+        }
+        response
+            .check_rcode()
+            .with_context(|| format!("{type} lookup for {domain} failed"))?;
+        return Ok(());
+    }
 
-#[derive(Debug)]
-struct Answer {
-    r#type: u16,
-    class: u16,
-    ttl: u32,
-    address: [u8; 4],
-}
+    // Use the nameservers from the command line, falling back to the system configuration.
+    let mut resolver = match servers {
+        servers if servers.is_empty() => Resolver::from_system()?,
+        servers => Resolver::new(servers),
+    };
+    resolver.options = options;
+    resolver.edns = edns;
+
+    // Send a query for the domain to the nameservers and wait for a response.
+    let Response {
+        message: response,
+        server,
+        elapsed,
+    } = resolver.send(&domain, r#type)?;
 
-impl TryFrom<&[u8]> for Answers {
-    type Error = Error;
-
-    // Parse a byte buffer into a list of Answers. This expects the entire DNS response, so it can
-    // parse the header for the count of answers. This also assumes each answer has the same size
-    // and that the answer names are compressed to 2 bytes.
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        //                                 1  1  1  1  1  1
-        //   0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                      ID                       |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |QR|   Opcode  |AA|TC|RD|RA|   Z    |   RCODE   |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                    QDCOUNT                    |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                    ANCOUNT                    |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                    NSCOUNT                    |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                    ARCOUNT                    |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        let num_answers = u16::from_be_bytes([
-            *value.get(6).ok_or(anyhow!("not enough bytes"))?,
-            *value.get(7).ok_or(anyhow!("not enough bytes"))?,
-        ]);
-        const ANSWER_SIZE: usize = 16;
-        let answer_start = value.len() - (num_answers as usize * ANSWER_SIZE);
-        let answer_bytes: Vec<&[u8]> = (0..num_answers as usize)
-            .map(|ofs| {
-                let start = answer_start + ofs * ANSWER_SIZE;
-                value.get(start..start + ANSWER_SIZE)
-            })
-            .collect::<Option<_>>()
-            .ok_or(anyhow!("not enough bytes"))?;
-        Ok(Answers(
-            answer_bytes
-                .into_iter()
-                .map(Answer::try_from)
-                .collect::<Result<_, _>>()?,
-        ))
+    match output {
+        // Display the answers, followed by the server's EDNS parameters if it sent any.
+        Output::Short => {
+            response
+                .check_rcode()
+                .with_context(|| format!("{type} lookup for {domain} failed"))?;
+            println!("{type} response for {domain} from {server}");
+            for answer in &response.answers {
+                println!("{}", answer);
+            }
+            if let Some(edns) = response.edns() {
+                println!("{edns}");
+            }
+        }
+        // Display the whole response, even if it has an error RCODE.
+        Output::Verbose => {
+            let verbose = Verbose {
+                response: &response,
+                server,
+                elapsed,
+            };
+            println!("{verbose}");
+            response.check_rcode()?;
+        }
+        // Serialize the whole response, even if it has an error RCODE.
+        Output::Json => {
+            let json = Json::new(&response, server, elapsed);
+            println!("{}", serde_json::to_string_pretty(&json)?);
+            response.check_rcode()?;
+        }
     }
+    Ok(())
 }
 
-impl TryFrom<&[u8]> for Answer {
-    type Error = Error;
-
-    // Parse a byte buffer into a single Answer. The name is assumed to be compressed to 2 bytes,
-    // and the data is assumed to be 4 bytes.
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        //                                 1  1  1  1  1  1
-        //   0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                                               |
-        // /                                               /
-        // /                      NAME                     /
-        // |                                               |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                      TYPE                     |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                     CLASS                     |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                      TTL                      |
-        // |                                               |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        // |                   RDLENGTH                    |
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--|
-        // /                     RDATA                     /
-        // /                                               /
-        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-        let get_u16 = |ofs: usize| -> Result<u16, Error> {
-            Ok(u16::from_be_bytes([
-                *value.get(ofs).ok_or(anyhow!("not enough bytes"))?,
-                *value.get(ofs + 1).ok_or(anyhow!("not enough bytes"))?,
-            ]))
-        };
-        let get_u32 = |ofs: usize| -> Result<u32, Error> {
-            Ok(u32::from_be_bytes([
-                *value.get(ofs).ok_or(anyhow!("not enough bytes"))?,
-                *value.get(ofs + 1).ok_or(anyhow!("not enough bytes"))?,
-                *value.get(ofs + 2).ok_or(anyhow!("not enough bytes"))?,
-                *value.get(ofs + 3).ok_or(anyhow!("not enough bytes"))?,
-            ]))
+// Resolve every name in the batch file concurrently, printing the answers to each one.
+fn run_batch(
+    batch: Batch,
+    r#type: RecordType,
+    servers: Vec<SocketAddr>,
+    options: Options,
+    edns: Option<Edns>,
+) -> Result<(), Error> {
+    let mut input = String::new();
+    match batch.file.as_str() {
+        "-" => io::stdin().read_to_string(&mut input)?,
+        file => fs::File::open(file)
+            .and_then(|mut f| f.read_to_string(&mut input))
+            .with_context(|| format!("could not read {file}"))?,
+    };
+    let names: Vec<String> = input
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect();
+    let total = names.len();
+    let servers = match servers {
+        servers if servers.is_empty() => config::system_servers()?,
+        servers => servers,
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let mut failed = 0;
+    runtime.block_on(async {
+        let mut resolver = AsyncResolver::new(servers).await?;
+        resolver.options = options;
+        resolver.edns = edns;
+        let on_result = |name: &str, result: Result<Vec<_>, Error>| match result {
+            Ok(answers) if answers.is_empty() => println!("{name}: no {type} records"),
+            Ok(answers) => answers.iter().for_each(|answer| println!("{answer}")),
+            Err(err) => {
+                eprintln!("Error: {err:#}");
+                failed += 1;
+            }
         };
-        Ok(Answer {
-            r#type: get_u16(2)?,
-            class: get_u16(4)?,
-            ttl: get_u32(6)?,
-            address: get_u32(12)?.to_be_bytes(),
-        })
+        let resolver = Arc::new(resolver);
+        batch::lookup_all(resolver, names, r#type, batch.limit, batch.order, on_result).await;
+        Ok::<_, Error>(())
+    })?;
+    if failed > 0 {
+        bail!("{failed} of {total} lookups failed");
     }
+    Ok(())
 }
 
-impl Display for Answer {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}.{}.{}.{} (TTL = {})",
-            self.address[0], self.address[1], self.address[2], self.address[3], self.ttl
-        )
-    }
+// Answer queries for the names in a zone file, until interrupted.
+fn run_server(serve: Serve) -> Result<(), Error> {
+    let text = fs::read_to_string(&serve.file)
+        .with_context(|| format!("could not read {}", serve.file))?;
+    let zone = Zone::parse(&text, serve.origin)
+        .with_context(|| format!("could not load zone file {}", serve.file))?;
+    let udp = UdpSocket::bind(serve.listen)
+        .with_context(|| format!("could not listen on {}", serve.listen))?;
+    let tcp = TcpListener::bind(serve.listen)
+        .with_context(|| format!("could not listen on {}", serve.listen))?;
+    eprintln!(
+        "Serving {} records for {} on {}",
+        zone.len(),
+        zone.origin,
+        serve.listen
+    );
+    server::run(Server::new(zone), udp, tcp)
+}
+
+// Forward queries from local clients to the nameservers, caching the answers, until interrupted.
+fn run_forwarder(
+    listen: SocketAddr,
+    servers: Vec<SocketAddr>,
+    options: Options,
+) -> Result<(), Error> {
+    let servers = match servers {
+        servers if servers.is_empty() => config::system_servers()?,
+        servers => servers,
+    };
+    let mut forwarder = Forwarder::new(servers.clone());
+    forwarder.options = options;
+    let udp = UdpSocket::bind(listen).with_context(|| format!("could not listen on {listen}"))?;
+    let tcp = TcpListener::bind(listen).with_context(|| format!("could not listen on {listen}"))?;
+    let upstream: Vec<String> = servers.iter().map(SocketAddr::to_string).collect();
+    eprintln!("Forwarding queries on {listen} to {}", upstream.join(", "));
+    server::run(forwarder, udp, tcp)
+}
+
+// Transfer a zone from the first nameserver and print it in zone file format. The changes of an
+// incremental transfer are printed under comments saying whether they were deleted or added.
+fn run_transfer(
+    zone: &str,
+    transfer: Transfer,
+    servers: Vec<SocketAddr>,
+    options: Options,
+) -> Result<(), Error> {
+    let server = match servers.first() {
+        Some(server) => *server,
+        None => *config::system_servers()?
+            .first()
+            .ok_or(anyhow!("no nameservers configured"))?,
+    };
+    let kind = match transfer {
+        Transfer::Axfr => "AXFR",
+        Transfer::Ixfr(_) => "IXFR",
+    };
+    println!("; {kind} of {zone} from {server}");
+    let mut section = Section::Zone;
+    let count = xfr::transfer(zone, transfer, server, options.timeout, |s, record| {
+        if s != section {
+            match s {
+                Section::Deleted => println!("; deleted"),
+                Section::Added => println!("; added"),
+                Section::Zone => println!(";"),
+            }
+            section = s;
+        }
+        println!("{}", Presentation(record));
+    })
+    .with_context(|| format!("{kind} of {zone} from {server} failed"))?;
+    eprintln!("; {count} records");
+    Ok(())
 }
 
-// Convenience trait implementation so we can iterate over the Answers type.
-impl IntoIterator for Answers {
-    type Item = Answer;
-    type IntoIter = <Vec<Answer> as IntoIterator>::IntoIter;
-    fn into_iter(self) -> Self::IntoIter {
-        self.0.into_iter()
+// Send a dynamic update to the first nameserver, which should be the zone's primary.
+fn run_update(update: Update, servers: Vec<SocketAddr>, options: Options) -> Result<(), Error> {
+    let server = *servers
+        .first()
+        .ok_or(anyhow!("--update requires the --server to send it to"))?;
+    update.send(server, &options)?;
+    println!(
+        "Updated {} on {server}: {} prerequisites, {} changes",
+        update.zone,
+        update.prerequisites.len(),
+        update.changes.len()
+    );
+    Ok(())
+}
+
+// How to display the response.
+enum Output {
+    // Only the answers.
+    Short,
+    // Every section of the message, like dig.
+    Verbose,
+    // Every section of the message as JSON.
+    Json,
+}
+
+// Command line arguments:
+// dns-client [-t TYPE] [-s SERVER]... [--timeout SECONDS] [--retries N] [--tcp]
+//            [--tls] [--tls-name NAME] [--doh URL [--doh-get]] [--no-edns] [--bufsize BYTES] [--dnssec] [--subnet ADDRESS/PREFIX] [--cookie]
+//            [-v | --json | --trace | --validate [--trust-anchor DS]...] <domain | address>
+// dns-client [-t TYPE] [-s SERVER]... ... --file PATH [--limit N] [--unordered]
+// dns-client -t AXFR | -t IXFR --serial N [-s SERVER] [--timeout SECONDS] <zone>
+// dns-client --update ZONE -s SERVER [--key [hmac-sha256:]NAME:SECRET] [--tcp]
+//            [--require NAME [TYPE [RDATA]]]... [--require-absent NAME [TYPE]]...
+//            [--add RECORD]... [--delete NAME [TYPE [RDATA]]]...
+// dns-client --serve ZONEFILE [--origin NAME] [--listen ADDRESS]
+// dns-client --forward [-s SERVER]... [--timeout SECONDS] [--retries N] [--tcp] [--tls ...]
+//            [--doh URL] [--listen ADDRESS]
+struct Args {
+    domain: Option<String>,
+    r#type: RecordType,
+    servers: Vec<SocketAddr>,
+    options: Options,
+    edns: Option<Edns>,
+    output: Output,
+    trace: bool,
+    // Names to look up in bulk instead of a single domain.
+    batch: Option<Batch>,
+    // Validate the response with DNSSEC, trusting the keys of these DS records.
+    anchors: Option<Vec<TrustAnchor>>,
+    // Answer queries from a zone file instead of sending any.
+    serve: Option<Serve>,
+    // Run a caching forwarder on this address instead of sending a query.
+    forward: Option<SocketAddr>,
+    // Transfer the zone named by the domain over TCP instead of sending a query.
+    transfer: Option<Transfer>,
+    // Send a dynamic update instead of a query.
+    update: Option<Update>,
+}
+
+// An authoritative server for a zone.
+struct Serve {
+    // Path of the zone file in master file format.
+    file: String,
+    // The origin for relative names until the file sets one with $ORIGIN.
+    origin: Name,
+    // Address to listen on for both UDP and TCP.
+    listen: SocketAddr,
+}
+
+// A list of names to resolve, one per line.
+struct Batch {
+    // Path of the file to read names from, or - for stdin.
+    file: String,
+    // How many lookups may be in flight at once.
+    limit: usize,
+    order: Order,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, Error> {
+        let mut domain = None;
+        let mut r#type = None;
+        let mut servers = Vec::new();
+        let mut tls = false;
+        let mut tls_name = None;
+        let mut doh = None;
+        let mut doh_method = Method::Post;
+        let mut options = Options::default();
+        let mut edns = Edns::default();
+        let mut no_edns = false;
+        let mut output = Output::Short;
+        let mut trace = false;
+        let mut file = None;
+        let mut limit = 100;
+        let mut order = Order::Input;
+        let mut validate = false;
+        let mut anchors = Vec::new();
+        let mut serve = None;
+        let mut forward = false;
+        let mut origin = Name::root();
+        let mut listen = config::parse_server("127.0.0.1")?;
+        let mut serial = None;
+        let mut zone = None;
+        let mut key = None;
+        // Prerequisites and changes in the order given, parsed once the zone is known.
+        let mut edits = Vec::new();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(anyhow!("{arg} requires a value"));
+            match arg.as_str() {
+                "-t" | "--type" => r#type = Some(value()?.parse()?),
+                "-s" | "--server" => servers.push(value()?),
+                "--timeout" => options.timeout = Duration::try_from_secs_f64(value()?.parse()?)?,
+                "--retries" => options.retries = value()?.parse()?,
+                "--tcp" => options.tcp = true,
+                "--tls" => tls = true,
+                "--tls-name" => tls_name = Some(value()?),
+                "--doh" => doh = Some(value()?),
+                "--doh-get" => doh_method = Method::Get,
+                "--no-edns" => no_edns = true,
+                "--bufsize" => edns.payload_size = value()?.parse()?,
+                "--dnssec" => edns.dnssec_ok = true,
+                "--subnet" => edns
+                    .options
+                    .push(EdnsOption::parse_client_subnet(&value()?)?),
+                "--cookie" => edns.options.push(EdnsOption::new_cookie()),
+                "-v" | "--verbose" => output = Output::Verbose,
+                "--json" => output = Output::Json,
+                "--trace" => trace = true,
+                "-f" | "--file" => file = Some(value()?),
+                "--limit" => limit = value()?.parse()?,
+                "--unordered" => order = Order::Arrival,
+                "--validate" => validate = true,
+                "--trust-anchor" => anchors.push(value()?.parse()?),
+                "--serve" => serve = Some(value()?),
+                "--origin" => origin = value()?.parse()?,
+                "--forward" => forward = true,
+                "--listen" => listen = config::parse_server(&value()?)?,
+                "--serial" => serial = Some(value()?.parse()?),
+                "--update" => zone = Some(value()?.parse::<Name>()?),
+                "--key" => key = Some(value()?.parse::<Key>()?),
+                "--require" | "--require-absent" | "--add" | "--delete" => {
+                    edits.push((arg.clone(), value()?))
+                }
+                _ if arg.starts_with('-') => bail!("unknown option {arg}"),
+                _ => domain = Some(arg),
+            }
+        }
+        if file.is_some() && (trace || !matches!(output, Output::Short)) {
+            bail!("--file can't be combined with -v, --json or --trace");
+        }
+        // Trust anchors replace the root's, so a private signed zone can be validated as well.
+        let validate = validate || !anchors.is_empty();
+        if validate && (trace || file.is_some() || !matches!(output, Output::Short)) {
+            bail!("--validate can't be combined with -v, --json, --trace or --file");
+        }
+        if validate && anchors.is_empty() {
+            anchors = dnssec::root_anchors();
+        }
+        if serve.is_some() && (domain.is_some() || file.is_some()) {
+            bail!("--serve can't be combined with a domain or --file");
+        }
+        if forward && (serve.is_some() || domain.is_some() || file.is_some() || validate) {
+            bail!("--forward can't be combined with a domain, --serve, --file or --validate");
+        }
+        // An IXFR needs the serial of the version of the zone we already have.
+        let transfer = match (r#type, serial) {
+            (Some(RecordType::AXFR), None) => Some(Transfer::Axfr),
+            (Some(RecordType::IXFR), Some(serial)) => Some(Transfer::Ixfr(serial)),
+            (Some(RecordType::IXFR), None) => bail!("-t IXFR requires a --serial"),
+            (_, Some(_)) => bail!("--serial requires -t IXFR"),
+            _ => None,
+        };
+        if transfer.is_some()
+            && (trace || validate || file.is_some() || !matches!(output, Output::Short))
+        {
+            bail!(
+                "zone transfers can't be combined with -v, --json, --trace, --validate or --file"
+            );
+        }
+        if transfer.is_some() && (tls || tls_name.is_some() || doh.is_some()) {
+            bail!("zone transfers can't be combined with --tls or --doh");
+        }
+        let update = match zone {
+            Some(zone) => {
+                let mut update = Update::new(zone);
+                update.key = key;
+                for (arg, value) in edits {
+                    let zone = &update.zone;
+                    match arg.as_str() {
+                        "--require" => update
+                            .prerequisites
+                            .push(Prerequisite::parse(&value, zone)?),
+                        "--require-absent" => update
+                            .prerequisites
+                            .push(Prerequisite::parse_absent(&value, zone)?),
+                        "--add" => update.changes.push(Change::parse_add(&value, zone)?),
+                        _ => update.changes.push(Change::parse_delete(&value, zone)?),
+                    }
+                }
+                if update.changes.is_empty() {
+                    bail!("--update requires at least one --add or --delete");
+                }
+                Some(update)
+            }
+            None if key.is_some() || !edits.is_empty() => {
+                bail!("--key, --require, --require-absent, --add and --delete require --update")
+            }
+            None => None,
+        };
+        if update.is_some()
+            && (domain.is_some()
+                || file.is_some()
+                || serve.is_some()
+                || forward
+                || trace
+                || validate
+                || !matches!(output, Output::Short))
+        {
+            bail!("--update can't be combined with a domain or other modes");
+        }
+        if update.is_some() && (tls || tls_name.is_some() || doh.is_some()) {
+            bail!("--update can't be combined with --tls or --doh");
+        }
+        // Servers are given on port 853 for DNS-over-TLS, unless they have a port of their own. The
+        // certificate of each one must be valid for the TLS name, or its address without one.
+        let tls = tls || tls_name.is_some();
+        let port = if tls { DOT_PORT } else { config::DNS_PORT };
+        let servers = servers
+            .iter()
+            .map(|s| config::parse_server_with_port(s, port))
+            .collect::<Result<Vec<_>, _>>()?;
+        if doh.is_some() && (tls || !servers.is_empty()) {
+            bail!("--doh can't be combined with --tls or --server");
+        }
+        if tls {
+            if servers.is_empty() {
+                bail!("--tls requires a --server");
+            }
+            let mut config = Tls::new()?;
+            config.server_name = tls_name;
+            options.tls = Some(config);
+        }
+
+        // Queries go to the addresses of the DoH endpoint's host.
+        let servers = match doh {
+            Some(endpoint) => {
+                let mut doh = Doh::new(&endpoint, Tls::new()?)?;
+                doh.method = doh_method;
+                let servers = doh.servers()?;
+                options.doh = Some(doh);
+                servers
+            }
+            None => servers,
+        };
+
+        // An address is looked up in reverse: ask for the PTR records of its in-addr.arpa or
+        // ip6.arpa name, unless another type was given.
+        let (domain, r#type) = match domain.as_deref().map(str::parse::<IpAddr>) {
+            Some(Ok(ip)) => (
+                Some(Name::reverse(ip).to_string()),
+                r#type.unwrap_or(RecordType::PTR),
+            ),
+            _ => (domain, r#type.unwrap_or(RecordType::A)),
+        };
+        Ok(Args {
+            domain,
+            r#type,
+            servers,
+            options,
+            edns: (!no_edns).then_some(edns),
+            output,
+            trace,
+            batch: file.map(|file| Batch { file, limit, order }),
+            anchors: validate.then_some(anchors),
+            serve: serve.map(|file| Serve {
+                file,
+                origin,
+                listen,
+            }),
+            forward: forward.then_some(listen),
+            transfer,
+            update,
+        })
     }
 }
@@ -0,0 +1,757 @@
+use crate::edns::Edns;
+use crate::error::ResponseError;
+use crate::name::Name;
+use crate::rdata::{RData, RecordType};
+use anyhow::{anyhow, bail, Error};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+// Maximum length of a domain name on the wire, including length octets and the root label.
+const MAX_NAME_LENGTH: usize = 255;
+
+// Classes that UPDATE messages give special meanings (RFC 2136 section 1.3).
+const NONE: u16 = 254;
+const ANY: u16 = 255;
+
+// A fully parsed DNS message. Sections are kept in the order they appear on the wire.
+#[derive(Debug, Clone, Serialize)]
+pub struct Message {
+    pub header: Header,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub authority: Vec<Record>,
+    pub additional: Vec<Record>,
+}
+
+//                                 1  1  1  1  1  1
+//   0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                      ID                       |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |QR|   Opcode  |AA|TC|RD|RA|   Z    |   RCODE   |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                    QDCOUNT                    |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                    ANCOUNT                    |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                    NSCOUNT                    |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                    ARCOUNT                    |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+#[derive(Debug, Clone, Serialize)]
+pub struct Header {
+    pub id: u16,
+    pub flags: Flags,
+    pub qdcount: u16,
+    pub ancount: u16,
+    pub nscount: u16,
+    pub arcount: u16,
+}
+
+// The second word of the header. The Z bits from RFC 1035 have since been assigned to the AD
+// and CD flags by RFC 4035:
+//
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |QR|   Opcode  |AA|TC|RD|RA| Z|AD|CD|   RCODE   |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Flags {
+    // Response (set) or query (clear).
+    pub qr: bool,
+    pub opcode: u8,
+    // Authoritative answer.
+    pub aa: bool,
+    // Truncated: the message didn't fit in the UDP payload.
+    pub tc: bool,
+    // Recursion desired.
+    pub rd: bool,
+    // Recursion available.
+    pub ra: bool,
+    pub z: bool,
+    // Authentic data: the resolver validated the answer with DNSSEC.
+    pub ad: bool,
+    // Checking disabled: the resolver should skip DNSSEC validation.
+    pub cd: bool,
+    pub rcode: u8,
+}
+
+impl From<u16> for Flags {
+    fn from(value: u16) -> Self {
+        let bit = |n: u16| value & (1 << n) != 0;
+        Flags {
+            qr: bit(15),
+            opcode: ((value >> 11) & 0xf) as u8,
+            aa: bit(10),
+            tc: bit(9),
+            rd: bit(8),
+            ra: bit(7),
+            z: bit(6),
+            ad: bit(5),
+            cd: bit(4),
+            rcode: (value & 0xf) as u8,
+        }
+    }
+}
+
+impl From<Flags> for u16 {
+    fn from(flags: Flags) -> Self {
+        let bit = |set: bool, n: u16| u16::from(set) << n;
+        bit(flags.qr, 15)
+            | ((flags.opcode & 0xf) as u16) << 11
+            | bit(flags.aa, 10)
+            | bit(flags.tc, 9)
+            | bit(flags.rd, 8)
+            | bit(flags.ra, 7)
+            | bit(flags.z, 6)
+            | bit(flags.ad, 5)
+            | bit(flags.cd, 4)
+            | (flags.rcode & 0xf) as u16
+    }
+}
+
+//                                 1  1  1  1  1  1
+//   0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                                               |
+// /                     QNAME                     /
+// /                                               /
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                     QTYPE                     |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                     QCLASS                    |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+#[derive(Debug, Clone, Serialize)]
+pub struct Question {
+    pub name: Name,
+    pub r#type: RecordType,
+    pub class: u16,
+}
+
+//                                 1  1  1  1  1  1
+//   0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                                               |
+// /                                               /
+// /                      NAME                     /
+// |                                               |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                      TYPE                     |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                     CLASS                     |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                      TTL                      |
+// |                                               |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                   RDLENGTH                    |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--|
+// /                     RDATA                     /
+// /                                               /
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+#[derive(Debug, Clone, Serialize)]
+pub struct Record {
+    pub name: Name,
+    pub r#type: RecordType,
+    pub class: u16,
+    pub ttl: u32,
+    pub rdata: RData,
+}
+
+impl TryFrom<&[u8]> for Message {
+    type Error = Error;
+
+    // Parse an entire DNS response. Each section is read in order using the counts from the
+    // header, so records may have any size and names may be compressed anywhere in the message.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let mut r = Reader::new(value);
+        let header = Header {
+            id: r.u16()?,
+            flags: r.u16()?.into(),
+            qdcount: r.u16()?,
+            ancount: r.u16()?,
+            nscount: r.u16()?,
+            arcount: r.u16()?,
+        };
+        let questions = (0..header.qdcount)
+            .map(|_| r.question())
+            .collect::<Result<_, _>>()?;
+        let answers = (0..header.ancount)
+            .map(|_| r.record())
+            .collect::<Result<_, _>>()?;
+        let authority = (0..header.nscount)
+            .map(|_| r.record())
+            .collect::<Result<_, _>>()?;
+        let additional = (0..header.arcount)
+            .map(|_| r.record())
+            .collect::<Result<_, _>>()?;
+        Ok(Message {
+            header,
+            questions,
+            answers,
+            authority,
+            additional,
+        })
+    }
+}
+
+impl Message {
+    // The EDNS parameters from the OPT pseudo-record, if the message has one.
+    pub fn edns(&self) -> Option<Edns> {
+        self.additional.iter().find_map(Edns::from_record)
+    }
+
+    // The full response code. EDNS extends the 4 bit RCODE in the header with 8 more bits.
+    pub fn rcode(&self) -> u16 {
+        let extended = self.edns().map_or(0, |edns| edns.extended_rcode as u16);
+        extended << 4 | self.header.flags.rcode as u16
+    }
+
+    // Turn an error response code into a typed error.
+    pub fn check_rcode(&self) -> Result<(), ResponseError> {
+        match self.rcode() {
+            0 => Ok(()),
+            rcode => Err(ResponseError::from(rcode)),
+        }
+    }
+
+    // Encode the message in wire format. Names are compressed as described in RFC 1035 section
+    // 4.1.4: a name, or the tail of one, that was already written is replaced by a pointer to it.
+    // The counts in the header are taken from the sections rather than the header fields.
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        let mut names = Compression::default();
+        buf.extend(self.header.id.to_be_bytes());
+        buf.extend(u16::from(self.header.flags).to_be_bytes());
+        for count in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authority.len(),
+            self.additional.len(),
+        ] {
+            buf.extend(u16::try_from(count)?.to_be_bytes());
+        }
+        for question in &self.questions {
+            names.write(&question.name, &mut buf);
+            buf.extend(u16::from(question.r#type).to_be_bytes());
+            buf.extend(question.class.to_be_bytes());
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authority)
+            .chain(&self.additional)
+        {
+            record.encode(&mut buf, &mut names)?;
+        }
+        Ok(buf)
+    }
+}
+
+impl Record {
+    // Write the record in wire format. Only the names in the RDATA of types from RFC 1035 may be
+    // compressed, since other implementations may not know where names are in newer types
+    // (RFC 3597 section 4).
+    fn encode(&self, buf: &mut Vec<u8>, names: &mut Compression) -> Result<(), Error> {
+        names.write(&self.name, buf);
+        buf.extend(u16::from(self.r#type).to_be_bytes());
+        buf.extend(self.class.to_be_bytes());
+        buf.extend(self.ttl.to_be_bytes());
+        let start = buf.len();
+        buf.extend([0, 0]);
+        match &self.rdata {
+            RData::NS(name) | RData::CNAME(name) | RData::PTR(name) => names.write(name, buf),
+            RData::MX {
+                preference,
+                exchange,
+            } => {
+                buf.extend(preference.to_be_bytes());
+                names.write(exchange, buf);
+            }
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                names.write(mname, buf);
+                names.write(rname, buf);
+                for n in [serial, refresh, retry, expire, minimum] {
+                    buf.extend(n.to_be_bytes());
+                }
+            }
+            rdata => rdata.encode(buf)?,
+        }
+        let rdlength = u16::try_from(buf.len() - start - 2)?;
+        buf[start..start + 2].copy_from_slice(&rdlength.to_be_bytes());
+        Ok(())
+    }
+}
+
+// The offsets of the names written to a message so far. Every suffix of a name is remembered, so
+// www.example.com can point at example.com from an earlier example.com or mail.example.com.
+#[derive(Default)]
+struct Compression(HashMap<Name, u16>);
+
+impl Compression {
+    // Pointers are 14 bits, so names further into the message can't be pointed at.
+    const MAX_OFFSET: usize = 0x3fff;
+
+    fn write(&mut self, name: &Name, buf: &mut Vec<u8>) {
+        let mut suffix = name.clone();
+        while let Some(parent) = suffix.parent() {
+            if let Some(&offset) = self.0.get(&suffix) {
+                buf.extend((0xc000 | offset).to_be_bytes());
+                return;
+            }
+            let label = &suffix.labels()[0];
+            let offset = buf.len();
+            buf.push(label.len() as u8);
+            buf.extend(label);
+            if offset <= Self::MAX_OFFSET {
+                self.0.insert(suffix, offset as u16);
+            }
+            suffix = parent;
+        }
+        buf.push(0);
+    }
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} (TTL = {})",
+            self.name, self.r#type, self.rdata, self.ttl
+        )
+    }
+}
+
+// Cursor over a complete DNS message. The whole message is kept around so compression pointers,
+// which are offsets from the start of the message, can be followed.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.buf.get(self.pos..end))
+            .ok_or(anyhow!("not enough bytes"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, Error> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, Error> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn question(&mut self) -> Result<Question, Error> {
+        Ok(Question {
+            name: self.name()?,
+            r#type: self.u16()?.into(),
+            class: self.u16()?,
+        })
+    }
+
+    pub(crate) fn record(&mut self) -> Result<Record, Error> {
+        let name = self.name()?;
+        let r#type = self.u16()?.into();
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let rdlength = self.u16()?;
+        // UPDATE messages use empty RDATA of any type with class ANY or NONE to refer to whole
+        // RRsets (RFC 2136 section 2.4), so it is kept as it is rather than rejected. Anywhere
+        // else, empty RDATA must be valid for the type.
+        let rdata = match class {
+            ANY | NONE if rdlength == 0 && r#type != RecordType::OPT => RData::Unknown(Vec::new()),
+            _ => RData::decode(self, r#type, rdlength)?,
+        };
+        Ok(Record {
+            name,
+            r#type,
+            class,
+            ttl,
+            rdata,
+        })
+    }
+
+    // Read a possibly compressed domain name. A name is a sequence of length-prefixed labels
+    // ending in either a zero-length label or a pointer to a name earlier in the message:
+    //
+    // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+    // | 1  1|                OFFSET                   |
+    // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+    //
+    // Pointers are only followed if they point strictly before the labels currently being read.
+    // Every jump therefore moves backwards in the message, which guarantees termination even for
+    // maliciously crafted pointer loops.
+    pub(crate) fn name(&mut self) -> Result<Name, Error> {
+        let mut labels = Vec::new();
+        let mut wire_length = 1;
+        let mut inner = Reader::new(self.buf);
+        inner.pos = self.pos;
+        let mut run_start = self.pos;
+        let mut end = None;
+        loop {
+            let length = inner.u8()?;
+            match length & 0xc0 {
+                0x00 if length == 0 => break,
+                0x00 => {
+                    wire_length += 1 + length as usize;
+                    if wire_length > MAX_NAME_LENGTH {
+                        bail!("name exceeds {MAX_NAME_LENGTH} bytes");
+                    }
+                    labels.push(inner.bytes(length as usize)?.to_vec());
+                }
+                0xc0 => {
+                    let offset = u16::from_be_bytes([length & 0x3f, inner.u8()?]) as usize;
+                    end.get_or_insert(inner.pos);
+                    if offset >= run_start {
+                        bail!("compression pointer loop at offset {offset}");
+                    }
+                    inner.pos = offset;
+                    run_start = offset;
+                }
+                _ => bail!("unsupported label type {:#04x}", length & 0xc0),
+            }
+        }
+        self.pos = end.unwrap_or(inner.pos);
+        Name::from_labels(labels)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::output::{Json, Verbose};
+    use quickcheck::{Arbitrary, Gen};
+    use quickcheck_macros::quickcheck;
+    use std::time::Duration;
+
+    // Header for a response with one question and the given record counts.
+    fn header(ancount: u8, nscount: u8, arcount: u8) -> Vec<u8> {
+        vec![0, 1, 0x81, 0x80, 0, 1, 0, ancount, 0, nscount, 0, arcount]
+    }
+
+    #[test]
+    fn parse_cname_chain() {
+        let mut msg = header(2, 0, 0);
+        // www.example.com. A IN at offset 12.
+        msg.extend(b"\x03www\x07example\x03com\x00\x00\x01\x00\x01");
+        // www.example.com. CNAME example.com. (compressed to offset 16)
+        msg.extend(b"\xc0\x0c\x00\x05\x00\x01\x00\x00\x01\x00\x00\x02\xc0\x10");
+        // example.com. A 93.184.216.34
+        msg.extend(b"\xc0\x10\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x5d\xb8\xd8\x22");
+
+        let msg = Message::try_from(msg.as_slice()).unwrap();
+        assert_eq!(msg.questions[0].name.to_string(), "www.example.com.");
+        assert_eq!(msg.answers.len(), 2);
+        assert_eq!(msg.answers[0].r#type, RecordType::CNAME);
+        assert_eq!(msg.answers[0].ttl, 256);
+        assert_eq!(msg.answers[0].rdata.to_string(), "example.com.");
+        assert_eq!(msg.answers[1].name.to_string(), "example.com.");
+        assert_eq!(msg.answers[1].rdata.to_string(), "93.184.216.34");
+    }
+
+    #[test]
+    fn encode_with_compression() {
+        let mut msg = header(2, 0, 0);
+        msg.extend(b"\x03www\x07example\x03com\x00\x00\x01\x00\x01");
+        msg.extend(b"\xc0\x0c\x00\x05\x00\x01\x00\x00\x01\x00\x00\x02\xc0\x10");
+        msg.extend(b"\xc0\x10\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x5d\xb8\xd8\x22");
+
+        // Every name after the question points back into it, just like the original.
+        let encoded = Message::try_from(msg.as_slice()).unwrap().encode().unwrap();
+        assert_eq!(encoded, msg);
+    }
+
+    #[test]
+    fn flags_round_trip() {
+        let flags = Flags::from(0x8583);
+        assert!(flags.qr && flags.aa && flags.rd && flags.ra);
+        assert!(!flags.tc && !flags.ad);
+        assert_eq!(flags.rcode, 3);
+        assert_eq!(u16::from(flags), 0x8583);
+    }
+
+    #[test]
+    fn parse_all_sections() {
+        let mut msg = header(0, 1, 1);
+        msg.extend(b"\x07example\x03com\x00\x00\x01\x00\x01");
+        // example.com. NS a.iana-servers.net.
+        msg.extend(b"\xc0\x0c\x00\x02\x00\x01\x00\x00\x0e\x10\x00\x14");
+        msg.extend(b"\x01a\x0ciana-servers\x03net\x00");
+        // a.iana-servers.net. AAAA (compressed to the NS rdata at offset 41)
+        msg.extend(b"\xc0\x29\x00\x1c\x00\x01\x00\x00\x0e\x10\x00\x10");
+        msg.extend([
+            0x20, 0x01, 0x05, 0x00, 0x00, 0x8f, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x53,
+        ]);
+
+        let msg = Message::try_from(msg.as_slice()).unwrap();
+        assert!(msg.answers.is_empty());
+        assert_eq!(msg.authority[0].rdata.to_string(), "a.iana-servers.net.");
+        assert_eq!(msg.additional[0].name.to_string(), "a.iana-servers.net.");
+        assert_eq!(msg.additional[0].rdata.to_string(), "2001:500:8f::53");
+    }
+
+    #[test]
+    fn reject_pointer_loop() {
+        let mut msg = header(0, 0, 0);
+        // Question name is a pointer to itself.
+        msg.extend(b"\xc0\x0c\x00\x01\x00\x01");
+        assert!(Message::try_from(msg.as_slice()).is_err());
+
+        let mut msg = header(0, 0, 0);
+        // A label followed by a pointer back to the start of the same name.
+        msg.extend(b"\x01a\xc0\x0c\x00\x01\x00\x01");
+        assert!(Message::try_from(msg.as_slice()).is_err());
+    }
+
+    #[test]
+    fn parse_empty_rdata() {
+        // example.com. A with no RDATA, in class ANY as UPDATE messages use it, then in IN.
+        let mut msg = header(1, 0, 0);
+        msg.extend(b"\x07example\x03com\x00\x00\x06\x00\x01");
+        msg.extend(b"\xc0\x0c\x00\x01\x00\xff\x00\x00\x00\x00\x00\x00");
+        let parsed = Message::try_from(msg.as_slice()).unwrap();
+        assert_eq!(parsed.answers[0].rdata, RData::Unknown(Vec::new()));
+        let class = msg.len() - 7;
+        msg[class] = 1;
+        assert!(Message::try_from(msg.as_slice()).is_err());
+    }
+
+    #[test]
+    fn reject_truncated() {
+        let mut msg = header(1, 0, 0);
+        msg.extend(b"\x07example\x03com\x00\x00\x01\x00\x01");
+        msg.extend(b"\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x5d");
+        assert!(Message::try_from(msg.as_slice()).is_err());
+        assert!(Message::try_from(&msg[..5]).is_err());
+    }
+
+    // A random message made of the records we know how to encode. Names are drawn from a small
+    // alphabet so that many of them share suffixes and get compressed.
+    #[derive(Debug, Clone)]
+    struct Arb(Message);
+
+    impl Arbitrary for Arb {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let questions = (0..usize::arbitrary(g) % 3)
+                .map(|_| Question {
+                    name: name(g),
+                    r#type: u16::arbitrary(g).into(),
+                    class: u16::arbitrary(g),
+                })
+                .collect();
+            let mut records = || (0..usize::arbitrary(g) % 4).map(|_| record(g)).collect();
+            let (answers, authority, additional) = (records(), records(), records());
+            Arb(Message {
+                header: Header {
+                    id: u16::arbitrary(g),
+                    flags: u16::arbitrary(g).into(),
+                    qdcount: 0,
+                    ancount: 0,
+                    nscount: 0,
+                    arcount: 0,
+                },
+                questions,
+                answers,
+                authority,
+                additional,
+            })
+        }
+    }
+
+    fn name(g: &mut Gen) -> Name {
+        let labels = (0..usize::arbitrary(g) % 4)
+            .map(|_| bytes(g, 1, 10))
+            .collect();
+        Name::from_labels(labels).unwrap()
+    }
+
+    // Between min and max bytes, mostly from a small alphabet.
+    fn bytes(g: &mut Gen, min: usize, max: usize) -> Vec<u8> {
+        let length = min + usize::arbitrary(g) % (max - min + 1);
+        (0..length)
+            .map(|_| *g.choose(b"abc-0.\\\xff").unwrap())
+            .collect()
+    }
+
+    fn record(g: &mut Gen) -> Record {
+        let (r#type, rdata) = match u8::arbitrary(g) % 14 {
+            0 => (RecordType::A, RData::A(u32::arbitrary(g).into())),
+            1 => (RecordType::AAAA, RData::AAAA(u128::arbitrary(g).into())),
+            2 => (RecordType::NS, RData::NS(name(g))),
+            3 => (RecordType::CNAME, RData::CNAME(name(g))),
+            4 => (
+                RecordType::MX,
+                RData::MX {
+                    preference: u16::arbitrary(g),
+                    exchange: name(g),
+                },
+            ),
+            5 => {
+                let strings = (0..1 + usize::arbitrary(g) % 3).map(|_| bytes(g, 0, 20));
+                (RecordType::TXT, RData::TXT(strings.collect()))
+            }
+            6 => (
+                RecordType::SOA,
+                RData::SOA {
+                    mname: name(g),
+                    rname: name(g),
+                    serial: u32::arbitrary(g),
+                    refresh: u32::arbitrary(g),
+                    retry: u32::arbitrary(g),
+                    expire: u32::arbitrary(g),
+                    minimum: u32::arbitrary(g),
+                },
+            ),
+            7 => (
+                RecordType::SRV,
+                RData::SRV {
+                    priority: u16::arbitrary(g),
+                    weight: u16::arbitrary(g),
+                    port: u16::arbitrary(g),
+                    target: name(g),
+                },
+            ),
+            8 => (
+                RecordType::DS,
+                RData::DS {
+                    key_tag: u16::arbitrary(g),
+                    algorithm: u8::arbitrary(g),
+                    digest_type: u8::arbitrary(g),
+                    digest: bytes(g, 0, 32),
+                },
+            ),
+            9 => (
+                RecordType::RRSIG,
+                RData::RRSIG {
+                    type_covered: u16::arbitrary(g).into(),
+                    algorithm: u8::arbitrary(g),
+                    labels: u8::arbitrary(g),
+                    original_ttl: u32::arbitrary(g),
+                    expiration: u32::arbitrary(g),
+                    inception: u32::arbitrary(g),
+                    key_tag: u16::arbitrary(g),
+                    signer: name(g),
+                    signature: bytes(g, 0, 64),
+                },
+            ),
+            10 => {
+                // The bitmap is sorted and has no duplicates once it has been through the wire.
+                let mut types: Vec<u16> = Vec::arbitrary(g);
+                types.sort_unstable();
+                types.dedup();
+                let types = types.into_iter().map(RecordType::from).collect();
+                let rdata = RData::NSEC {
+                    next: name(g),
+                    types,
+                };
+                (RecordType::NSEC, rdata)
+            }
+            11 => (
+                RecordType::CAA,
+                RData::CAA {
+                    flags: u8::arbitrary(g),
+                    tag: bytes(g, 0, 10),
+                    value: bytes(g, 0, 20),
+                },
+            ),
+            12 => (
+                RecordType::TSIG,
+                RData::TSIG {
+                    algorithm: name(g),
+                    time_signed: u64::arbitrary(g) >> 16,
+                    fudge: u16::arbitrary(g),
+                    mac: bytes(g, 0, 32),
+                    original_id: u16::arbitrary(g),
+                    error: u16::arbitrary(g),
+                    other: bytes(g, 0, 6),
+                },
+            ),
+            _ => (RecordType::Unknown(65280), RData::Unknown(bytes(g, 1, 20))),
+        };
+        Record {
+            name: name(g),
+            r#type,
+            class: u16::arbitrary(g),
+            ttl: u32::arbitrary(g),
+            rdata,
+        }
+    }
+
+    // Everything we do with a parsed message, which must not panic whatever it contains.
+    fn exercise(message: &Message) {
+        let server = ([127, 0, 0, 1], 53).into();
+        let verbose = Verbose {
+            response: message,
+            server,
+            elapsed: Duration::ZERO,
+        };
+        verbose.to_string();
+        serde_json::to_string(&Json::new(message, server, Duration::ZERO)).unwrap();
+        let _ = message.check_rcode();
+        if let Ok(encoded) = message.encode() {
+            Message::try_from(encoded.as_slice()).unwrap();
+        }
+    }
+
+    #[quickcheck]
+    fn encode_parse_round_trip(message: Arb) -> bool {
+        let encoded = message.0.encode().unwrap();
+        let parsed = Message::try_from(encoded.as_slice()).unwrap();
+        exercise(&parsed);
+        let sections = |m: &Message| format!("{:?}", (&m.questions, &m.answers, &m.authority));
+        parsed.encode().unwrap() == encoded
+            && sections(&parsed) == sections(&message.0)
+            && format!("{:?}", parsed.additional) == format!("{:?}", message.0.additional)
+    }
+
+    // Parsing a damaged message either fails or gives a message that can be used like any other.
+    #[quickcheck]
+    fn parse_damaged_messages(message: Arb, damage: Vec<(usize, u8)>, length: usize) -> bool {
+        let mut encoded = message.0.encode().unwrap();
+        for (i, byte) in damage {
+            let i = i % encoded.len();
+            encoded[i] = byte;
+        }
+        encoded.truncate(length % (encoded.len() + 1));
+        if let Ok(parsed) = Message::try_from(encoded.as_slice()) {
+            exercise(&parsed);
+        }
+        true
+    }
+
+    #[quickcheck]
+    fn parse_arbitrary_bytes(bytes: Vec<u8>) -> bool {
+        if let Ok(parsed) = Message::try_from(bytes.as_slice()) {
+            exercise(&parsed);
+        }
+        true
+    }
+}
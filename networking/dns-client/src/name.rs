@@ -0,0 +1,322 @@
+use anyhow::{anyhow, bail, Error};
+use serde::{Serialize, Serializer};
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+// Limits on the wire format length of a label and a whole name, including the length octets.
+const MAX_LABEL_LENGTH: usize = 63;
+const MAX_NAME_LENGTH: usize = 255;
+
+// A domain name stored as its sequence of labels, not including the terminating root label.
+// Labels are kept as raw bytes because the wire format allows any octet in a label.
+#[derive(Debug, Clone, Default)]
+pub struct Name(Vec<Vec<u8>>);
+
+impl Name {
+    // The root name ".", which has no labels.
+    pub fn root() -> Self {
+        Name(Vec::new())
+    }
+
+    // Build a name from its labels, which are held to the same limits as parsed names so the name
+    // can always be encoded.
+    pub fn from_labels(labels: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if labels
+            .iter()
+            .any(|label| label.is_empty() || label.len() > MAX_LABEL_LENGTH)
+        {
+            bail!("labels must be between 1 and {MAX_LABEL_LENGTH} bytes long");
+        }
+        let length = labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1;
+        if length > MAX_NAME_LENGTH {
+            bail!("name is {length} bytes long, more than the limit of {MAX_NAME_LENGTH}");
+        }
+        Ok(Name(labels))
+    }
+
+    pub fn labels(&self) -> &[Vec<u8>] {
+        &self.0
+    }
+
+    // The name to look up PTR records for an address under (RFC 1035 section 3.5, RFC 3596
+    // section 2.5): the octets of an IPv4 address or the hex digits of an IPv6 address, in
+    // reverse order, under in-addr.arpa or ip6.arpa.
+    //
+    //     192.0.2.1   => 1.2.0.192.in-addr.arpa.
+    //     2001:db8::1 => 1.0.0.0.[...].8.b.d.0.1.0.0.2.ip6.arpa.
+    pub fn reverse(ip: IpAddr) -> Self {
+        let (mut labels, suffix): (Vec<_>, _) = match ip {
+            IpAddr::V4(ip) => (
+                ip.octets().iter().rev().map(|o| o.to_string()).collect(),
+                "in-addr",
+            ),
+            IpAddr::V6(ip) => (
+                ip.octets()
+                    .iter()
+                    .rev()
+                    .flat_map(|o| [o & 0xf, o >> 4])
+                    .map(|nibble| format!("{nibble:x}"))
+                    .collect(),
+                "ip6",
+            ),
+        };
+        labels.extend([suffix.to_string(), "arpa".to_string()]);
+        Name(labels.into_iter().map(String::into_bytes).collect())
+    }
+
+    // The name with ASCII letters lowercased, as used in the canonical form of records (RFC 4034
+    // section 6.2).
+    pub fn to_lowercase(&self) -> Self {
+        Name(self.0.iter().map(|l| l.to_ascii_lowercase()).collect())
+    }
+
+    // The name with its leftmost label removed, or None for the root.
+    pub fn parent(&self) -> Option<Self> {
+        (!self.is_root()).then(|| Name(self.0[1..].to_vec()))
+    }
+
+    // Write the name in uncompressed wire format.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        for label in &self.0 {
+            buf.push(label.len() as u8);
+            buf.extend(label);
+        }
+        buf.push(0);
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Whether this name is equal to or below the other name, e.g. www.example.com is a subdomain
+    // of example.com and of the root.
+    pub fn is_subdomain_of(&self, other: &Name) -> bool {
+        self.0.len() >= other.0.len()
+            && self.0[self.0.len() - other.0.len()..]
+                .iter()
+                .zip(other.0.iter())
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+// Parse a dotted name in master file format. A trailing dot is optional, and "." or "" is the root
+// name. Inside a label, \X stands for the character X (so "\." is a dot rather than a separator)
+// and \DDD for the byte with decimal value DDD. Labels with Unicode characters are converted to
+// their ASCII (punycode) form as described in UTS #46, and the ideographic full stops it treats
+// as dots separate labels too.
+//
+// Labels must be between 1 and 63 bytes long, and the whole name must fit in 255 bytes in wire
+// format (RFC 1035 section 2.3.4).
+impl FromStr for Name {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s == "." {
+            return Ok(Name::root());
+        }
+        let mut labels = Vec::new();
+        let mut label = Label::default();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '.' | '\u{3002}' | '\u{ff0e}' | '\u{ff61}' => {
+                    labels.push(std::mem::take(&mut label).finish(s)?)
+                }
+                '\\' => {
+                    label.escaped = true;
+                    match chars.next() {
+                        Some(d) if d.is_ascii_digit() => {
+                            let digits: String = [Some(d), chars.next(), chars.next()]
+                                .into_iter()
+                                .collect::<Option<_>>()
+                                .ok_or(anyhow!("incomplete escape in {s}"))?;
+                            let byte = digits
+                                .parse()
+                                .map_err(|_| anyhow!("invalid escape \\{digits} in {s}"))?;
+                            label.bytes.push(byte);
+                        }
+                        Some(c) => label.push(c),
+                        None => bail!("{s} ends with a backslash"),
+                    }
+                }
+                c => label.push(c),
+            }
+        }
+        // Every character adds to the label, so it is only empty after a trailing dot.
+        if !label.bytes.is_empty() {
+            labels.push(label.finish(s)?);
+        }
+
+        let length: usize = labels.iter().map(|l| l.len() + 1).sum::<usize>() + 1;
+        if length > MAX_NAME_LENGTH {
+            bail!("{s} is {length} bytes long, more than the limit of {MAX_NAME_LENGTH}");
+        }
+        Ok(Name(labels))
+    }
+}
+
+// A label being parsed.
+#[derive(Default)]
+struct Label {
+    bytes: Vec<u8>,
+    // Whether the label has characters outside of ASCII, which need converting to punycode.
+    unicode: bool,
+    // Whether the label has any backslash escapes.
+    escaped: bool,
+}
+
+impl Label {
+    fn push(&mut self, c: char) {
+        self.unicode |= !c.is_ascii();
+        self.bytes
+            .extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+
+    fn finish(self, name: &str) -> Result<Vec<u8>, Error> {
+        if self.bytes.is_empty() {
+            bail!("{name} has an empty label");
+        }
+        let bytes = if self.unicode {
+            if self.escaped {
+                bail!("{name} has a label with both escapes and Unicode characters");
+            }
+            let label = String::from_utf8(self.bytes)?;
+            let ascii = idna::domain_to_ascii(&label)
+                .map_err(|_| anyhow!("{label} is not a valid internationalized label"))?;
+            if ascii.is_empty() || ascii.contains('.') {
+                bail!("{label} is not a valid internationalized label");
+            }
+            ascii.into_bytes()
+        } else {
+            self.bytes
+        };
+        if bytes.len() > MAX_LABEL_LENGTH {
+            bail!("{name} has a label longer than {MAX_LABEL_LENGTH} bytes");
+        }
+        Ok(bytes)
+    }
+}
+
+// Names are compared case-insensitively for ASCII letters (RFC 4343).
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(other.0.iter())
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for label in &self.0 {
+            label.len().hash(state);
+            for b in label {
+                b.to_ascii_lowercase().hash(state);
+            }
+        }
+    }
+}
+
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// Display the name in master file format with a trailing dot. Dots and backslashes inside a label
+// are escaped with a backslash, and non-printable bytes are written as \DDD.
+impl Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_root() {
+            return write!(f, ".");
+        }
+        for label in &self.0 {
+            for &b in label {
+                match b {
+                    b'.' | b'\\' => write!(f, "\\{}", b as char)?,
+                    0x21..=0x7e => write!(f, "{}", b as char)?,
+                    _ => write!(f, "\\{:03}", b)?,
+                }
+            }
+            write!(f, ".")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reverse_names() {
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        assert_eq!(Name::reverse(ip).to_string(), "1.2.0.192.in-addr.arpa.");
+
+        let ip: IpAddr = "2001:db8::567:89ab".parse().unwrap();
+        assert_eq!(
+            Name::reverse(ip).to_string(),
+            "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa."
+        );
+    }
+
+    #[test]
+    fn parse_names() {
+        let name: Name = "www.Example.com.".parse().unwrap();
+        assert_eq!(name.labels(), [&b"www"[..], b"Example", b"com"]);
+        assert_eq!(name, "www.example.com".parse().unwrap());
+        assert!("".parse::<Name>().unwrap().is_root());
+        assert!(".".parse::<Name>().unwrap().is_root());
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let name: Name = r"a\.b\\c\032d.example".parse().unwrap();
+        assert_eq!(name.labels(), [&b"a.b\\c d"[..], b"example"]);
+        // Display escapes the same characters, so names round trip.
+        assert_eq!(name.to_string(), r"a\.b\\c\032d.example.");
+        assert_eq!(name.to_string().parse::<Name>().unwrap(), name);
+
+        assert!(r"a\".parse::<Name>().is_err());
+        assert!(r"a\25".parse::<Name>().is_err());
+        assert!(r"a\256".parse::<Name>().is_err());
+    }
+
+    #[test]
+    fn parse_unicode() {
+        let name: Name = "Bücher.example".parse().unwrap();
+        assert_eq!(name.to_string(), "xn--bcher-kva.example.");
+        let name: Name = "例え。テスト".parse().unwrap();
+        assert_eq!(name.to_string(), "xn--r8jz45g.xn--zckzah.");
+    }
+
+    #[test]
+    fn reject_invalid_names() {
+        assert!("a..b".parse::<Name>().is_err());
+        assert!(".a".parse::<Name>().is_err());
+        assert!("a.b..".parse::<Name>().is_err());
+
+        let label = "a".repeat(63);
+        assert!(label.parse::<Name>().is_ok());
+        assert!(format!("{label}a").parse::<Name>().is_err());
+
+        // Four 63 byte labels take 4 * 64 + 1 = 257 bytes.
+        let name = [label.as_str(); 4].join(".");
+        assert!(name.parse::<Name>().is_err());
+        assert!(name[2..].parse::<Name>().is_ok());
+
+        // The same limits hold for names built from labels.
+        let label = label.into_bytes();
+        assert!(Name::from_labels(vec![label.clone(); 3]).is_ok());
+        assert!(Name::from_labels(vec![label.clone(); 4]).is_err());
+        assert!(Name::from_labels(vec![[label.as_slice(), b"a"].concat()]).is_err());
+        assert!(Name::from_labels(vec![Vec::new()]).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use crate::message::{Message, Record};
+use crate::rdata::RecordType;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::ser::SerializeSeq;
+use serde::{Serialize, Serializer};
+use std::fmt::{self, Display};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+// Display a whole response in a layout similar to dig: the header, every section, and some
+// statistics about the exchange.
+pub struct Verbose<'a> {
+    pub response: &'a Message,
+    pub server: SocketAddr,
+    pub elapsed: Duration,
+}
+
+impl Display for Verbose<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Message {
+            header,
+            questions,
+            answers,
+            authority,
+            additional,
+        } = self.response;
+        let flags = header.flags;
+        writeln!(
+            f,
+            ";; ->>HEADER<<- opcode: {}, status: {}, id: {}",
+            opcode_name(flags.opcode),
+            rcode_name(self.response.rcode()),
+            header.id
+        )?;
+        let set_flags: Vec<_> = [
+            (flags.qr, "qr"),
+            (flags.aa, "aa"),
+            (flags.tc, "tc"),
+            (flags.rd, "rd"),
+            (flags.ra, "ra"),
+            (flags.ad, "ad"),
+            (flags.cd, "cd"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect();
+        writeln!(
+            f,
+            ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+            set_flags.join(" "),
+            header.qdcount,
+            header.ancount,
+            header.nscount,
+            header.arcount
+        )?;
+
+        if let Some(edns) = self.response.edns() {
+            writeln!(f, "\n;; OPT PSEUDOSECTION:\n{edns}")?;
+        }
+        writeln!(f, "\n;; QUESTION SECTION:")?;
+        for q in questions {
+            writeln!(f, ";{}\t\t{}\t{}", q.name, class_name(q.class), q.r#type)?;
+        }
+        for (title, records) in [
+            ("ANSWER", answers),
+            ("AUTHORITY", authority),
+            ("ADDITIONAL", additional),
+        ] {
+            // The OPT record is already shown in the pseudosection.
+            let records: Vec<_> = records
+                .iter()
+                .filter(|r| r.r#type != RecordType::OPT)
+                .collect();
+            if records.is_empty() {
+                continue;
+            }
+            writeln!(f, "\n;; {title} SECTION:")?;
+            for record in records {
+                writeln!(f, "{}", Presentation(record))?;
+            }
+        }
+
+        writeln!(f, "\n;; Query time: {} msec", self.elapsed.as_millis())?;
+        write!(f, ";; SERVER: {}#{}", self.server.ip(), self.server.port())
+    }
+}
+
+// The whole response along with details of the exchange, for machine-readable output.
+#[derive(Serialize)]
+pub struct Json<'a> {
+    pub server: SocketAddr,
+    pub query_time_ms: u128,
+    pub status: String,
+    pub message: &'a Message,
+}
+
+impl<'a> Json<'a> {
+    pub fn new(response: &'a Message, server: SocketAddr, elapsed: Duration) -> Self {
+        Json {
+            server,
+            query_time_ms: elapsed.as_millis(),
+            status: rcode_name(response.rcode()),
+            message: response,
+        }
+    }
+}
+
+// Display a record in master file format: name, TTL, class, type and RDATA separated by tabs.
+pub struct Presentation<'a>(pub &'a Record);
+
+impl Display for Presentation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let record = self.0;
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            record.name,
+            record.ttl,
+            class_name(record.class),
+            record.r#type,
+            record.rdata
+        )
+    }
+}
+
+pub fn class_name(class: u16) -> String {
+    match class {
+        1 => "IN".into(),
+        3 => "CH".into(),
+        4 => "HS".into(),
+        254 => "NONE".into(),
+        255 => "ANY".into(),
+        class => format!("CLASS{class}"),
+    }
+}
+
+fn opcode_name(opcode: u8) -> String {
+    match opcode {
+        0 => "QUERY".into(),
+        1 => "IQUERY".into(),
+        2 => "STATUS".into(),
+        4 => "NOTIFY".into(),
+        5 => "UPDATE".into(),
+        opcode => format!("OPCODE{opcode}"),
+    }
+}
+
+fn rcode_name(rcode: u16) -> String {
+    match rcode {
+        0 => "NOERROR".into(),
+        1 => "FORMERR".into(),
+        2 => "SERVFAIL".into(),
+        3 => "NXDOMAIN".into(),
+        4 => "NOTIMP".into(),
+        5 => "REFUSED".into(),
+        6 => "YXDOMAIN".into(),
+        7 => "YXRRSET".into(),
+        8 => "NXRRSET".into(),
+        9 => "NOTAUTH".into(),
+        10 => "NOTZONE".into(),
+        16 => "BADVERS".into(),
+        rcode => format!("RCODE{rcode}"),
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// Encode bytes in the base32hex alphabet without padding (RFC 4648 section 7), as used for NSEC3
+// hashes.
+pub fn base32hex(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+    let mut out = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut block = [0; 5];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let bits = block.iter().fold(0u64, |acc, &b| acc << 8 | b as u64);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            out.push(ALPHABET[(bits >> (35 - 5 * i) & 0x1f) as usize] as char);
+        }
+    }
+    out
+}
+
+// Serialize raw bytes as a base64 string.
+pub fn serialize_base64<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(bytes))
+}
+
+// Serialize raw bytes as a hex string.
+pub fn serialize_hex<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex(bytes))
+}
+
+// Serialize raw bytes as a string, replacing invalid UTF-8.
+pub fn serialize_lossy<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&String::from_utf8_lossy(bytes))
+}
+
+// Serialize a list of character-strings as strings, replacing invalid UTF-8.
+pub fn serialize_lossy_strings<S: Serializer>(
+    strings: &[Vec<u8>],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut seq = serializer.serialize_seq(Some(strings.len()))?;
+    for s in strings {
+        seq.serialize_element(&String::from_utf8_lossy(s))?;
+    }
+    seq.end()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn verbose_layout() {
+        let mut msg = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0];
+        msg.extend(b"\x07example\x03com\x00\x00\x01\x00\x01");
+        msg.extend(b"\xc0\x0c\x00\x01\x00\x01\x00\x00\x01\x2c\x00\x04\x5d\xb8\xd8\x22");
+        let response = Message::try_from(msg.as_slice()).unwrap();
+        let output = Verbose {
+            response: &response,
+            server: "1.1.1.1:53".parse().unwrap(),
+            elapsed: Duration::from_millis(12),
+        }
+        .to_string();
+        assert_eq!(
+            output,
+            ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4660\n\
+             ;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 0\n\
+             \n\
+             ;; QUESTION SECTION:\n\
+             ;example.com.\t\tIN\tA\n\
+             \n\
+             ;; ANSWER SECTION:\n\
+             example.com.\t300\tIN\tA\t93.184.216.34\n\
+             \n\
+             ;; Query time: 12 msec\n\
+             ;; SERVER: 1.1.1.1#53"
+        );
+    }
+
+    #[test]
+    fn json_layout() {
+        let mut msg = vec![0x12, 0x34, 0x81, 0x80, 0, 0, 0, 2, 0, 0, 0, 0];
+        msg.extend(b"\x07example\x03com\x00\x00\x0f\x00\x01\x00\x00\x01\x2c\x00\x09");
+        msg.extend(b"\x00\x0a\x04mail\xc0\x0c");
+        msg.extend(b"\xc0\x0c\x00\x10\x00\x01\x00\x00\x01\x2c\x00\x06\x05v=spf");
+        let response = Message::try_from(msg.as_slice()).unwrap();
+        let json = Json::new(
+            &response,
+            "1.1.1.1:53".parse().unwrap(),
+            Duration::from_millis(12),
+        );
+        let value = serde_json::to_value(&json).unwrap();
+        assert_eq!(value["status"], "NOERROR");
+        assert_eq!(value["message"]["header"]["flags"]["ra"], true);
+        let answers = &value["message"]["answers"];
+        assert_eq!(answers[0]["name"], "example.com.");
+        assert_eq!(answers[0]["type"], "MX");
+        assert_eq!(answers[0]["rdata"]["exchange"], "mail.example.com.");
+        assert_eq!(answers[1]["rdata"], serde_json::json!(["v=spf"]));
+    }
+}
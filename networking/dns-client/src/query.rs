@@ -0,0 +1,189 @@
+use crate::edns::Edns;
+use crate::message::{Flags, Message};
+use crate::name::Name;
+use crate::rdata::RecordType;
+use anyhow::{bail, Error};
+use std::io::Write;
+
+// Simplified DNS query for a provided domain's record of the given type.
+pub struct Query<T: AsRef<str>> {
+    pub id: u16,
+    pub domain: T,
+    pub r#type: RecordType,
+    // Ask the nameserver to resolve the name for us. Iterative resolution clears this to talk to
+    // authoritative servers directly.
+    pub recursion_desired: bool,
+    // Ask the nameserver not to validate DNSSEC signatures, so a validating client gets to see
+    // (and judge) bogus data for itself.
+    pub checking_disabled: bool,
+    // EDNS parameters to send in an OPT record, or None to send a plain RFC 1035 query.
+    pub edns: Option<Edns>,
+}
+
+impl<T: AsRef<str>> Query<T> {
+    // Create a query with a random transaction ID, so responses can't be predicted by an
+    // off-path attacker and concurrent queries can be told apart.
+    pub fn new(domain: T, r#type: RecordType) -> Self {
+        Query {
+            id: rand::random(),
+            domain,
+            r#type,
+            recursion_desired: true,
+            checking_disabled: false,
+            edns: Some(Edns::default()),
+        }
+    }
+
+    // Encode the query as bytes.
+    pub fn encode(&self, mut w: impl Write) -> Result<usize, Error> {
+        let mut nbytes = self.encode_header(&mut w)? + self.encode_question(&mut w)?;
+        if let Some(edns) = &self.edns {
+            nbytes += edns.encode(&mut w)?;
+        }
+        Ok(nbytes)
+    }
+
+    // Write the header as bytes. The header indicates we are only asking one question, plus an
+    // OPT record in the additional section when EDNS is used.
+    fn encode_header(&self, mut w: impl Write) -> Result<usize, Error> {
+        //                                 1  1  1  1  1  1
+        //   0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
+        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+        // |                      ID                       |
+        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+        // |QR|   Opcode  |AA|TC|RD|RA|   Z    |   RCODE   |
+        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+        // |                    QDCOUNT                    |
+        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+        // |                    ANCOUNT                    |
+        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+        // |                    NSCOUNT                    |
+        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+        // |                    ARCOUNT                    |
+        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+        let flags = Flags {
+            rd: self.recursion_desired,
+            cd: self.checking_disabled,
+            ..Flags::default()
+        };
+        w.write_all(&self.id.to_be_bytes())?;
+        w.write_all(&u16::from(flags).to_be_bytes())?;
+        w.write_all(&[
+            0, 1, // Question count: 1
+            0, 0, // Answer count: 0
+            0, 0, // Name server count: 0
+        ])?;
+        // Additional record count: 1 for the OPT record with EDNS, otherwise 0
+        w.write_all(&u16::from(self.edns.is_some()).to_be_bytes())?;
+        Ok(12)
+    }
+
+    // Encode the question as bytes, assuming the IN class.
+    fn encode_question(&self, mut w: impl Write) -> Result<usize, Error> {
+        //                                 1  1  1  1  1  1
+        //   0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
+        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+        // |                                               |
+        // /                     QNAME                     /
+        // /                                               /
+        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+        // |                     QTYPE                     |
+        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+        // |                     QCLASS                    |
+        // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+        let name: Name = self.domain.as_ref().parse()?;
+        let mut nbytes = 0;
+        for label in name.labels() {
+            let label_length = u8::try_from(label.len())?;
+            w.write_all(&[label_length])?;
+            w.write_all(label)?;
+            nbytes += label.len() + 1;
+        }
+        w.write_all(&[0])?;
+        nbytes += 1;
+        w.write_all(&u16::from(self.r#type).to_be_bytes())?;
+        w.write_all(&[
+            0, 1, // Question class: IN (internet)
+        ])?;
+        nbytes += 4;
+        Ok(nbytes)
+    }
+
+    // Check that a response belongs to this query: it must have our ID, be marked as a response
+    // to a standard query, and echo back the question we asked.
+    pub fn validate(&self, response: &Message) -> Result<(), Error> {
+        let header = &response.header;
+        if header.id != self.id {
+            bail!("ID {} does not match query ID {}", header.id, self.id);
+        }
+        if !header.flags.qr {
+            bail!("QR bit is not set");
+        }
+        if header.flags.opcode != 0 {
+            bail!("unexpected opcode {}", header.flags.opcode);
+        }
+        let name: Name = self.domain.as_ref().parse()?;
+        match response.questions.as_slice() {
+            [q] if q.name == name && q.r#type == self.r#type && q.class == 1 => Ok(()),
+            [q] => bail!("question {} {} does not match the query", q.name, q.r#type),
+            questions => bail!("expected 1 question, got {}", questions.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Build a response to the query by flipping the QR bit of the encoded query.
+    fn response(query: &Query<&str>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        query.encode(&mut buf).unwrap();
+        buf[2] |= 0x80;
+        buf
+    }
+
+    #[test]
+    fn validate_response() {
+        let query = Query::new("Example.com", RecordType::A);
+        let mut buf = response(&query);
+        // Names are compared case-insensitively.
+        buf[13] = b'e';
+        assert!(query
+            .validate(&Message::try_from(buf.as_slice()).unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn reject_mismatched_response() {
+        let query = Query::new("example.com", RecordType::A);
+
+        let mut buf = response(&query);
+        buf[0] ^= 0xff;
+        let err = query.validate(&Message::try_from(buf.as_slice()).unwrap());
+        assert!(err.unwrap_err().to_string().contains("ID"));
+
+        let mut buf = Vec::new();
+        query.encode(&mut buf).unwrap();
+        let err = query.validate(&Message::try_from(buf.as_slice()).unwrap());
+        assert!(err.unwrap_err().to_string().contains("QR"));
+
+        let other = Query {
+            id: query.id,
+            ..Query::new("example.org", RecordType::A)
+        };
+        let buf = response(&other);
+        assert!(query
+            .validate(&Message::try_from(buf.as_slice()).unwrap())
+            .is_err());
+
+        let other = Query {
+            id: query.id,
+            ..Query::new("example.com", RecordType::AAAA)
+        };
+        let buf = response(&other);
+        assert!(query
+            .validate(&Message::try_from(buf.as_slice()).unwrap())
+            .is_err());
+    }
+}
@@ -0,0 +1,861 @@
+use crate::edns::EdnsOption;
+use crate::message::Reader;
+use crate::name::Name;
+use crate::output::{
+    base32hex, hex, serialize_base64, serialize_hex, serialize_lossy, serialize_lossy_strings,
+};
+use anyhow::{anyhow, bail, Error};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Serialize, Serializer};
+use std::fmt::{self, Display};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+// The TYPE (and QTYPE) field of a resource record. Types we don't decode are kept as their
+// numeric value so they can still be queried and displayed.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    A,
+    NS,
+    CNAME,
+    SOA,
+    PTR,
+    MX,
+    TXT,
+    AAAA,
+    SRV,
+    OPT,
+    DS,
+    RRSIG,
+    NSEC,
+    DNSKEY,
+    NSEC3,
+    // Transaction signatures (RFC 8945).
+    TSIG,
+    // Query types for zone transfers (RFC 1995 and RFC 5936).
+    IXFR,
+    AXFR,
+    // Query type matching every type, also used by UPDATE to mean all RRsets at a name.
+    ANY,
+    CAA,
+    Unknown(u16),
+}
+
+impl From<u16> for RecordType {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => RecordType::A,
+            2 => RecordType::NS,
+            5 => RecordType::CNAME,
+            6 => RecordType::SOA,
+            12 => RecordType::PTR,
+            15 => RecordType::MX,
+            16 => RecordType::TXT,
+            28 => RecordType::AAAA,
+            33 => RecordType::SRV,
+            41 => RecordType::OPT,
+            43 => RecordType::DS,
+            46 => RecordType::RRSIG,
+            47 => RecordType::NSEC,
+            48 => RecordType::DNSKEY,
+            50 => RecordType::NSEC3,
+            250 => RecordType::TSIG,
+            251 => RecordType::IXFR,
+            252 => RecordType::AXFR,
+            255 => RecordType::ANY,
+            257 => RecordType::CAA,
+            n => RecordType::Unknown(n),
+        }
+    }
+}
+
+impl From<RecordType> for u16 {
+    fn from(value: RecordType) -> Self {
+        match value {
+            RecordType::A => 1,
+            RecordType::NS => 2,
+            RecordType::CNAME => 5,
+            RecordType::SOA => 6,
+            RecordType::PTR => 12,
+            RecordType::MX => 15,
+            RecordType::TXT => 16,
+            RecordType::AAAA => 28,
+            RecordType::SRV => 33,
+            RecordType::OPT => 41,
+            RecordType::DS => 43,
+            RecordType::RRSIG => 46,
+            RecordType::NSEC => 47,
+            RecordType::DNSKEY => 48,
+            RecordType::NSEC3 => 50,
+            RecordType::TSIG => 250,
+            RecordType::IXFR => 251,
+            RecordType::AXFR => 252,
+            RecordType::ANY => 255,
+            RecordType::CAA => 257,
+            RecordType::Unknown(n) => n,
+        }
+    }
+}
+
+// Parse a type mnemonic (case-insensitive) or the generic TYPEnnn form from RFC 3597.
+impl FromStr for RecordType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_uppercase();
+        Ok(match s.as_str() {
+            "A" => RecordType::A,
+            "NS" => RecordType::NS,
+            "CNAME" => RecordType::CNAME,
+            "SOA" => RecordType::SOA,
+            "PTR" => RecordType::PTR,
+            "MX" => RecordType::MX,
+            "TXT" => RecordType::TXT,
+            "AAAA" => RecordType::AAAA,
+            "SRV" => RecordType::SRV,
+            "OPT" => RecordType::OPT,
+            "DS" => RecordType::DS,
+            "RRSIG" => RecordType::RRSIG,
+            "NSEC" => RecordType::NSEC,
+            "DNSKEY" => RecordType::DNSKEY,
+            "NSEC3" => RecordType::NSEC3,
+            "TSIG" => RecordType::TSIG,
+            "IXFR" => RecordType::IXFR,
+            "AXFR" => RecordType::AXFR,
+            "ANY" => RecordType::ANY,
+            "CAA" => RecordType::CAA,
+            _ => s
+                .strip_prefix("TYPE")
+                .and_then(|n| n.parse::<u16>().ok())
+                .map(RecordType::from)
+                .ok_or(anyhow!("unknown record type {s}"))?,
+        })
+    }
+}
+
+impl Serialize for RecordType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordType::Unknown(n) => write!(f, "TYPE{n}"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+// Decoded RDATA of a resource record. The record type is stored next to the RDATA, so the JSON
+// form is just the contents of each variant.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum RData {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    NS(Name),
+    CNAME(Name),
+    PTR(Name),
+    MX {
+        preference: u16,
+        exchange: Name,
+    },
+    TXT(#[serde(serialize_with = "serialize_lossy_strings")] Vec<Vec<u8>>),
+    SOA {
+        mname: Name,
+        rname: Name,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    },
+    SRV {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: Name,
+    },
+    OPT(Vec<EdnsOption>),
+    // DNSSEC records (RFC 4034 and RFC 5155).
+    DS {
+        key_tag: u16,
+        algorithm: u8,
+        digest_type: u8,
+        #[serde(serialize_with = "serialize_hex")]
+        digest: Vec<u8>,
+    },
+    RRSIG {
+        type_covered: RecordType,
+        algorithm: u8,
+        labels: u8,
+        original_ttl: u32,
+        expiration: u32,
+        inception: u32,
+        key_tag: u16,
+        signer: Name,
+        #[serde(serialize_with = "serialize_base64")]
+        signature: Vec<u8>,
+    },
+    NSEC {
+        next: Name,
+        types: Vec<RecordType>,
+    },
+    DNSKEY {
+        flags: u16,
+        protocol: u8,
+        algorithm: u8,
+        #[serde(serialize_with = "serialize_base64")]
+        public_key: Vec<u8>,
+    },
+    NSEC3 {
+        hash_algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        #[serde(serialize_with = "serialize_hex")]
+        salt: Vec<u8>,
+        #[serde(serialize_with = "serialize_hex")]
+        next_hashed: Vec<u8>,
+        types: Vec<RecordType>,
+    },
+    CAA {
+        flags: u8,
+        #[serde(serialize_with = "serialize_lossy")]
+        tag: Vec<u8>,
+        #[serde(serialize_with = "serialize_lossy")]
+        value: Vec<u8>,
+    },
+    // Transaction signature (RFC 8945 section 4.2). The time is a 48 bit number of seconds.
+    TSIG {
+        algorithm: Name,
+        time_signed: u64,
+        fudge: u16,
+        #[serde(serialize_with = "serialize_base64")]
+        mac: Vec<u8>,
+        original_id: u16,
+        error: u16,
+        #[serde(serialize_with = "serialize_hex")]
+        other: Vec<u8>,
+    },
+    Unknown(#[serde(serialize_with = "serialize_hex")] Vec<u8>),
+}
+
+impl RData {
+    // Decode rdlength bytes of RDATA for the given type. The reader spans the whole message so
+    // that compressed names inside the RDATA can be followed.
+    pub(crate) fn decode(r: &mut Reader, r#type: RecordType, rdlength: u16) -> Result<Self, Error> {
+        let end = r.pos() + rdlength as usize;
+        let rdata = match r#type {
+            RecordType::A => {
+                let b = r.bytes(4)?;
+                RData::A(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+            }
+            RecordType::AAAA => {
+                let b: [u8; 16] = r.bytes(16)?.try_into()?;
+                RData::AAAA(Ipv6Addr::from(b))
+            }
+            RecordType::NS => RData::NS(r.name()?),
+            RecordType::CNAME => RData::CNAME(r.name()?),
+            RecordType::PTR => RData::PTR(r.name()?),
+            RecordType::MX => RData::MX {
+                preference: r.u16()?,
+                exchange: r.name()?,
+            },
+            RecordType::TXT => {
+                let mut strings = Vec::new();
+                while r.pos() < end {
+                    let length = r.u8()?;
+                    strings.push(r.bytes(length as usize)?.to_vec());
+                }
+                RData::TXT(strings)
+            }
+            RecordType::SOA => RData::SOA {
+                mname: r.name()?,
+                rname: r.name()?,
+                serial: r.u32()?,
+                refresh: r.u32()?,
+                retry: r.u32()?,
+                expire: r.u32()?,
+                minimum: r.u32()?,
+            },
+            RecordType::SRV => RData::SRV {
+                priority: r.u16()?,
+                weight: r.u16()?,
+                port: r.u16()?,
+                target: r.name()?,
+            },
+            RecordType::OPT => {
+                let mut options = Vec::new();
+                while r.pos() < end {
+                    let code = r.u16()?;
+                    let length = r.u16()?;
+                    options.push(EdnsOption::decode(code, r.bytes(length as usize)?));
+                }
+                RData::OPT(options)
+            }
+            RecordType::DS => RData::DS {
+                key_tag: r.u16()?,
+                algorithm: r.u8()?,
+                digest_type: r.u8()?,
+                digest: r.bytes(remaining(r, end)?)?.to_vec(),
+            },
+            RecordType::RRSIG => RData::RRSIG {
+                type_covered: r.u16()?.into(),
+                algorithm: r.u8()?,
+                labels: r.u8()?,
+                original_ttl: r.u32()?,
+                expiration: r.u32()?,
+                inception: r.u32()?,
+                key_tag: r.u16()?,
+                signer: r.name()?,
+                signature: r.bytes(remaining(r, end)?)?.to_vec(),
+            },
+            RecordType::NSEC => RData::NSEC {
+                next: r.name()?,
+                types: decode_type_bitmap(r.bytes(remaining(r, end)?)?)?,
+            },
+            RecordType::DNSKEY => RData::DNSKEY {
+                flags: r.u16()?,
+                protocol: r.u8()?,
+                algorithm: r.u8()?,
+                public_key: r.bytes(remaining(r, end)?)?.to_vec(),
+            },
+            RecordType::NSEC3 => {
+                let hash_algorithm = r.u8()?;
+                let flags = r.u8()?;
+                let iterations = r.u16()?;
+                let salt_length = r.u8()?;
+                let salt = r.bytes(salt_length as usize)?.to_vec();
+                let hash_length = r.u8()?;
+                RData::NSEC3 {
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                    next_hashed: r.bytes(hash_length as usize)?.to_vec(),
+                    types: decode_type_bitmap(r.bytes(remaining(r, end)?)?)?,
+                }
+            }
+            RecordType::CAA => {
+                let flags = r.u8()?;
+                let tag_length = r.u8()?;
+                let tag = r.bytes(tag_length as usize)?.to_vec();
+                RData::CAA {
+                    flags,
+                    tag,
+                    value: r.bytes(remaining(r, end)?)?.to_vec(),
+                }
+            }
+            RecordType::TSIG => {
+                let algorithm = r.name()?;
+                let time_signed = u64::from(r.u16()?) << 32 | u64::from(r.u32()?);
+                let fudge = r.u16()?;
+                let mac_size = r.u16()?;
+                let mac = r.bytes(mac_size as usize)?.to_vec();
+                let original_id = r.u16()?;
+                let error = r.u16()?;
+                let other_length = r.u16()?;
+                RData::TSIG {
+                    algorithm,
+                    time_signed,
+                    fudge,
+                    mac,
+                    original_id,
+                    error,
+                    other: r.bytes(other_length as usize)?.to_vec(),
+                }
+            }
+            // Query types never appear in records, so keep whatever is there as it is.
+            RecordType::IXFR | RecordType::AXFR | RecordType::ANY | RecordType::Unknown(_) => {
+                RData::Unknown(r.bytes(rdlength as usize)?.to_vec())
+            }
+        };
+        if r.pos() != end {
+            bail!("{} RDATA does not match RDLENGTH {}", r#type, rdlength);
+        }
+        Ok(rdata)
+    }
+
+    // Write the RDATA in wire format, without compressing any names. This is the canonical form
+    // used for DNSSEC once the names are lowercased.
+    pub fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        match self {
+            RData::A(addr) => buf.extend(addr.octets()),
+            RData::AAAA(addr) => buf.extend(addr.octets()),
+            RData::NS(name) | RData::CNAME(name) | RData::PTR(name) => name.encode(buf),
+            RData::MX {
+                preference,
+                exchange,
+            } => {
+                buf.extend(preference.to_be_bytes());
+                exchange.encode(buf);
+            }
+            RData::TXT(strings) => {
+                for s in strings {
+                    buf.push(u8::try_from(s.len())?);
+                    buf.extend(s);
+                }
+            }
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                mname.encode(buf);
+                rname.encode(buf);
+                for n in [serial, refresh, retry, expire, minimum] {
+                    buf.extend(n.to_be_bytes());
+                }
+            }
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                for n in [priority, weight, port] {
+                    buf.extend(n.to_be_bytes());
+                }
+                target.encode(buf);
+            }
+            RData::OPT(options) => {
+                for option in options {
+                    option.encode(&mut *buf)?;
+                }
+            }
+            RData::DS {
+                key_tag,
+                algorithm,
+                digest_type,
+                digest,
+            } => {
+                buf.extend(key_tag.to_be_bytes());
+                buf.extend([*algorithm, *digest_type]);
+                buf.extend(digest);
+            }
+            RData::RRSIG {
+                signer, signature, ..
+            } => {
+                self.encode_rrsig_fields(buf);
+                signer.encode(buf);
+                buf.extend(signature);
+            }
+            RData::NSEC { next, types } => {
+                next.encode(buf);
+                encode_type_bitmap(types, buf);
+            }
+            RData::DNSKEY {
+                flags,
+                protocol,
+                algorithm,
+                public_key,
+            } => {
+                buf.extend(flags.to_be_bytes());
+                buf.extend([*protocol, *algorithm]);
+                buf.extend(public_key);
+            }
+            RData::NSEC3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                next_hashed,
+                types,
+            } => {
+                buf.extend([*hash_algorithm, *flags]);
+                buf.extend(iterations.to_be_bytes());
+                buf.push(u8::try_from(salt.len())?);
+                buf.extend(salt);
+                buf.push(u8::try_from(next_hashed.len())?);
+                buf.extend(next_hashed);
+                encode_type_bitmap(types, buf);
+            }
+            RData::CAA { flags, tag, value } => {
+                buf.push(*flags);
+                buf.push(u8::try_from(tag.len())?);
+                buf.extend(tag);
+                buf.extend(value);
+            }
+            RData::TSIG {
+                algorithm,
+                time_signed,
+                fudge,
+                mac,
+                original_id,
+                error,
+                other,
+            } => {
+                algorithm.encode(buf);
+                buf.extend(&time_signed.to_be_bytes()[2..]);
+                buf.extend(fudge.to_be_bytes());
+                buf.extend(u16::try_from(mac.len())?.to_be_bytes());
+                buf.extend(mac);
+                buf.extend(original_id.to_be_bytes());
+                buf.extend(error.to_be_bytes());
+                buf.extend(u16::try_from(other.len())?.to_be_bytes());
+                buf.extend(other);
+            }
+            RData::Unknown(bytes) => buf.extend(bytes),
+        }
+        Ok(())
+    }
+
+    // Write the fixed size fields at the start of an RRSIG, which are also part of the data it
+    // signs. Does nothing for other types.
+    pub(crate) fn encode_rrsig_fields(&self, buf: &mut Vec<u8>) {
+        if let RData::RRSIG {
+            type_covered,
+            algorithm,
+            labels,
+            original_ttl,
+            expiration,
+            inception,
+            key_tag,
+            ..
+        } = self
+        {
+            buf.extend(u16::from(*type_covered).to_be_bytes());
+            buf.extend([*algorithm, *labels]);
+            for n in [original_ttl, expiration, inception] {
+                buf.extend(n.to_be_bytes());
+            }
+            buf.extend(key_tag.to_be_bytes());
+        }
+    }
+}
+
+// How many bytes of RDATA are left to read.
+fn remaining(r: &Reader, end: usize) -> Result<usize, Error> {
+    end.checked_sub(r.pos())
+        .ok_or(anyhow!("RDATA fields overrun RDLENGTH"))
+}
+
+// Decode the type bitmap of an NSEC or NSEC3 record (RFC 4034 section 4.1.2). Types are split
+// into windows of 256: each window has its number, the length of its bitmap, then the bitmap with
+// the most significant bit of the first byte standing for the first type in the window.
+fn decode_type_bitmap(mut bytes: &[u8]) -> Result<Vec<RecordType>, Error> {
+    let mut types = Vec::new();
+    while let [window, length, rest @ ..] = bytes {
+        let length = *length as usize;
+        if length == 0 || length > 32 || rest.len() < length {
+            bail!("invalid type bitmap window length {length}");
+        }
+        for (i, byte) in rest[..length].iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    let r#type = (*window as u16) << 8 | (i * 8 + bit) as u16;
+                    types.push(RecordType::from(r#type));
+                }
+            }
+        }
+        bytes = &rest[length..];
+    }
+    if !bytes.is_empty() {
+        bail!("truncated type bitmap");
+    }
+    Ok(types)
+}
+
+fn encode_type_bitmap(types: &[RecordType], buf: &mut Vec<u8>) {
+    let mut types: Vec<u16> = types.iter().map(|&t| t.into()).collect();
+    types.sort_unstable();
+    types.dedup();
+    for window in types.chunk_by(|a, b| a >> 8 == b >> 8) {
+        let mut bitmap = [0u8; 32];
+        for t in window {
+            let low = (t & 0xff) as usize;
+            bitmap[low / 8] |= 0x80 >> (low % 8);
+        }
+        let length = (window[window.len() - 1] & 0xff) as usize / 8 + 1;
+        buf.extend([(window[0] >> 8) as u8, length as u8]);
+        buf.extend(&bitmap[..length]);
+    }
+}
+
+// Display RDATA in master file format.
+impl Display for RData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RData::A(addr) => write!(f, "{addr}"),
+            RData::AAAA(addr) => write!(f, "{addr}"),
+            RData::NS(name) | RData::CNAME(name) | RData::PTR(name) => write!(f, "{name}"),
+            RData::MX {
+                preference,
+                exchange,
+            } => write!(f, "{preference} {exchange}"),
+            RData::TXT(strings) => {
+                for (i, s) in strings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write_quoted(f, s)?;
+                }
+                Ok(())
+            }
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => write!(
+                f,
+                "{mname} {rname} {serial} {refresh} {retry} {expire} {minimum}"
+            ),
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{priority} {weight} {port} {target}"),
+            RData::OPT(options) => {
+                for (i, option) in options.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{option}")?;
+                }
+                Ok(())
+            }
+            RData::DS {
+                key_tag,
+                algorithm,
+                digest_type,
+                digest,
+            } => write!(
+                f,
+                "{key_tag} {algorithm} {digest_type} {}",
+                hex(digest).to_uppercase()
+            ),
+            RData::RRSIG {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer,
+                signature,
+            } => {
+                write!(f, "{type_covered} {algorithm} {labels} {original_ttl} ")?;
+                write_timestamp(f, *expiration)?;
+                write!(f, " ")?;
+                write_timestamp(f, *inception)?;
+                write!(f, " {key_tag} {signer} {}", STANDARD.encode(signature))
+            }
+            RData::NSEC { next, types } => {
+                write!(f, "{next}")?;
+                types.iter().try_for_each(|t| write!(f, " {t}"))
+            }
+            RData::DNSKEY {
+                flags,
+                protocol,
+                algorithm,
+                public_key,
+            } => write!(
+                f,
+                "{flags} {protocol} {algorithm} {}",
+                STANDARD.encode(public_key)
+            ),
+            RData::NSEC3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                next_hashed,
+                types,
+            } => {
+                let salt = if salt.is_empty() {
+                    "-".into()
+                } else {
+                    hex(salt)
+                };
+                write!(f, "{hash_algorithm} {flags} {iterations} {salt} ")?;
+                write!(f, "{}", base32hex(next_hashed).to_uppercase())?;
+                types.iter().try_for_each(|t| write!(f, " {t}"))
+            }
+            RData::CAA { flags, tag, value } => {
+                write!(f, "{flags} {} ", String::from_utf8_lossy(tag))?;
+                write_quoted(f, value)
+            }
+            RData::TSIG {
+                algorithm,
+                time_signed,
+                fudge,
+                mac,
+                original_id,
+                error,
+                other,
+            } => {
+                write!(f, "{algorithm} {time_signed} {fudge} {} ", mac.len())?;
+                write!(
+                    f,
+                    "{} {original_id} {error} {}",
+                    STANDARD.encode(mac),
+                    other.len()
+                )?;
+                if !other.is_empty() {
+                    write!(f, " {}", hex(other))?;
+                }
+                Ok(())
+            }
+            // Generic RDATA format from RFC 3597.
+            RData::Unknown(bytes) => {
+                write!(f, "\\# {}", bytes.len())?;
+                if !bytes.is_empty() {
+                    write!(f, " ")?;
+                }
+                bytes.iter().try_for_each(|b| write!(f, "{b:02x}"))
+            }
+        }
+    }
+}
+
+// Write an RRSIG timestamp as YYYYMMDDHHmmSS in UTC (RFC 4034 section 3.2).
+fn write_timestamp(f: &mut fmt::Formatter<'_>, timestamp: u32) -> fmt::Result {
+    let (days, seconds) = (timestamp / 86400, timestamp % 86400);
+    // Convert days since 1970-01-01 to a date in the proleptic Gregorian calendar, counting
+    // 400 year eras from 0000-03-01 so leap days fall at the end of each year.
+    let days = days as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    write!(
+        f,
+        "{year:04}{month:02}{day:02}{:02}{:02}{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// Write a character-string surrounded by quotes, escaping quotes, backslashes and non-printable
+// bytes.
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &[u8]) -> fmt::Result {
+    write!(f, "\"")?;
+    for &b in s {
+        match b {
+            b'"' | b'\\' => write!(f, "\\{}", b as char)?,
+            0x20..=0x7e => write!(f, "{}", b as char)?,
+            _ => write!(f, "\\{:03}", b)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode(r#type: RecordType, rdata: &[u8]) -> Result<RData, Error> {
+        let mut r = Reader::new(rdata);
+        RData::decode(&mut r, r#type, rdata.len() as u16)
+    }
+
+    #[test]
+    fn parse_record_type() {
+        assert_eq!("aaaa".parse::<RecordType>().unwrap(), RecordType::AAAA);
+        assert_eq!("TYPE15".parse::<RecordType>().unwrap(), RecordType::MX);
+        assert_eq!(
+            "TYPE99".parse::<RecordType>().unwrap(),
+            RecordType::Unknown(99)
+        );
+        assert!("BOGUS".parse::<RecordType>().is_err());
+        assert_eq!(RecordType::Unknown(99).to_string(), "TYPE99");
+    }
+
+    #[test]
+    fn decode_mx() {
+        let rdata = decode(RecordType::MX, b"\x00\x0a\x04mail\x07example\x03com\x00").unwrap();
+        assert_eq!(rdata.to_string(), "10 mail.example.com.");
+    }
+
+    #[test]
+    fn decode_txt() {
+        let rdata = decode(RecordType::TXT, b"\x05v=spf\x08say \"hi\"").unwrap();
+        assert_eq!(rdata.to_string(), r#""v=spf" "say \"hi\"""#);
+    }
+
+    #[test]
+    fn decode_srv_and_caa() {
+        let rdata = decode(RecordType::SRV, b"\x00\x01\x00\x02\x13\xc4\x03sip\x00").unwrap();
+        assert_eq!(rdata.to_string(), "1 2 5060 sip.");
+        let rdata = decode(RecordType::CAA, b"\x00\x05issueletsencrypt.org").unwrap();
+        assert_eq!(rdata.to_string(), "0 issue \"letsencrypt.org\"");
+    }
+
+    #[test]
+    fn decode_dnssec_records() {
+        let mut rrsig = b"\x00\x01\x0d\x02\x00\x00\x0e\x10".to_vec();
+        rrsig.extend(b"\x65\x5f\x3a\x00\x65\x3c\x7e\x00\x30\x39\x07example\x00\x01\x02\x03");
+        let rdata = decode(RecordType::RRSIG, &rrsig).unwrap();
+        assert_eq!(
+            rdata.to_string(),
+            "A 13 2 3600 20231123113944 20231028032032 12345 example. AQID"
+        );
+
+        let nsec = b"\x04host\x07example\x00\x00\x06\x40\x01\x00\x00\x00\x03\x01\x01\x40";
+        let rdata = decode(RecordType::NSEC, nsec).unwrap();
+        assert_eq!(rdata.to_string(), "host.example. A MX RRSIG NSEC CAA");
+
+        let ds = b"\x30\x39\x08\x02\xab\xcd";
+        assert_eq!(
+            decode(RecordType::DS, ds).unwrap().to_string(),
+            "12345 8 2 ABCD"
+        );
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let records: [(RecordType, &[u8]); 6] = [
+            (RecordType::MX, b"\x00\x0a\x04mail\x07example\x00"),
+            (RecordType::TXT, b"\x05v=spf\x00"),
+            (
+                RecordType::NSEC,
+                b"\x01a\x00\x00\x06\x40\x01\x00\x00\x00\x03\x01\x01\x40",
+            ),
+            (RecordType::DNSKEY, b"\x01\x01\x03\x0d\x01\x02\x03\x04"),
+            (
+                RecordType::TSIG,
+                b"\x0bhmac-sha256\x00\x00\x00\x65\x53\xf1\x00\x01\x2c\x00\x02\xab\xcd\
+                  \x12\x34\x00\x00\x00\x00",
+            ),
+            (RecordType::Unknown(99), b"\x01\x02"),
+        ];
+        for (r#type, wire) in records {
+            let mut buf = Vec::new();
+            decode(r#type, wire).unwrap().encode(&mut buf).unwrap();
+            assert_eq!(buf, wire, "{type}");
+        }
+    }
+
+    #[test]
+    fn reject_rdlength_mismatch() {
+        let mut r = Reader::new(b"\x01\x02\x03\x04\x05");
+        assert!(RData::decode(&mut r, RecordType::A, 5).is_err());
+        assert!(decode(RecordType::AAAA, &[0; 4]).is_err());
+        assert!(decode(RecordType::A, &[]).is_err());
+    }
+}
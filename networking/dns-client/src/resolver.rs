@@ -0,0 +1,174 @@
+use crate::cache::Cache;
+use crate::config;
+use crate::edns::Edns;
+use crate::message::{Message, Record};
+use crate::name::Name;
+use crate::query::Query;
+use crate::rdata::{RData, RecordType};
+use crate::transport::{self, Options};
+use anyhow::{Context, Error};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+// A stub resolver that sends queries to a list of recursive nameservers.
+//
+//     let resolver = Resolver::from_system()?;
+//     for record in resolver.lookup("example.com", RecordType::AAAA)? {
+//         println!("{}", record.rdata);
+//     }
+pub struct Resolver {
+    // Nameservers to query, in order of preference.
+    pub servers: Vec<SocketAddr>,
+    // Timeouts, retries and transport.
+    pub options: Options,
+    // EDNS parameters sent with every query, or None to send plain RFC 1035 queries.
+    pub edns: Option<Edns>,
+    // Cache for the results of lookups, or None to always ask the nameservers.
+    pub cache: Option<Cache>,
+}
+
+// A response along with details of the exchange that produced it.
+pub struct Response {
+    pub message: Message,
+    pub server: SocketAddr,
+    pub elapsed: Duration,
+}
+
+impl Resolver {
+    pub fn new(servers: Vec<SocketAddr>) -> Self {
+        Resolver {
+            servers,
+            options: Options::default(),
+            edns: Some(Edns::default()),
+            cache: None,
+        }
+    }
+
+    // Use the nameservers configured in /etc/resolv.conf.
+    pub fn from_system() -> Result<Self, Error> {
+        Ok(Resolver::new(config::system_servers()?))
+    }
+
+    // Look up the records of the given type for a name. This returns the whole answer section,
+    // which includes any CNAME records that led to the answer. Error RCODEs are returned as a
+    // ResponseError. Results are served from the cache when there is one.
+    pub fn lookup(&self, name: &str, r#type: RecordType) -> Result<Vec<Record>, Error> {
+        let context = || format!("{type} lookup for {name} failed");
+        if let Some(cache) = &self.cache {
+            if let Some(result) = cache.get(&name.parse()?, r#type, 1) {
+                return result.with_context(context);
+            }
+        }
+        let response = self.send(name, r#type)?;
+        if let Some(cache) = &self.cache {
+            cache.insert(&response.message);
+        }
+        response.message.check_rcode().with_context(context)?;
+        Ok(response.message.answers)
+    }
+
+    // Look up the names an address points back to with PTR records.
+    pub fn lookup_addr(&self, ip: IpAddr) -> Result<Vec<Name>, Error> {
+        let records = self.lookup(&Name::reverse(ip).to_string(), RecordType::PTR)?;
+        Ok(records
+            .into_iter()
+            .filter_map(|r| match r.rdata {
+                RData::PTR(name) => Some(name),
+                _ => None,
+            })
+            .collect())
+    }
+
+    // Send a query and return the full response, whatever its RCODE.
+    pub fn send(&self, name: &str, r#type: RecordType) -> Result<Response, Error> {
+        let query = Query {
+            edns: self.edns.clone(),
+            ..Query::new(name, r#type)
+        };
+        let start = Instant::now();
+        let (message, server) = transport::exchange(&query, &self.servers, &self.options)?;
+        Ok(Response {
+            message,
+            server,
+            elapsed: start.elapsed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ResponseError;
+    use crate::test_support::reply;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    // Answer one query with the given RCODE and an A record for 192.0.2.1.
+    fn respond(server: UdpSocket, rcode: u8) {
+        let mut buf = [0; 512];
+        let (n, addr) = server.recv_from(&mut buf).unwrap();
+        let answers = [RData::A([192, 0, 2, 1].into())];
+        server
+            .send_to(&reply(&buf[..n], rcode, &answers), addr)
+            .unwrap();
+    }
+
+    #[test]
+    fn lookup_answers() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolver = Resolver::new(vec![server.local_addr().unwrap()]);
+        let handle = thread::spawn(move || respond(server, 0));
+        let records = resolver.lookup("example.com", RecordType::A).unwrap();
+        assert_eq!(records[0].rdata.to_string(), "192.0.2.1");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn lookup_nxdomain() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolver = Resolver::new(vec![server.local_addr().unwrap()]);
+        let handle = thread::spawn(move || respond(server, 3));
+        let err = resolver.lookup("example.com", RecordType::A).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&ResponseError::NXDomain));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn lookup_cached() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut resolver = Resolver::new(vec![server.local_addr().unwrap()]);
+        resolver.cache = Some(Cache::default());
+        let handle = thread::spawn(move || respond(server, 0));
+        // Only the first lookup reaches the server, which answers a single query.
+        for _ in 0..2 {
+            let records = resolver.lookup("example.com", RecordType::A).unwrap();
+            assert_eq!(records[0].rdata.to_string(), "192.0.2.1");
+        }
+        let stats = resolver.cache.as_ref().unwrap().stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn lookup_addr_ptr() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolver = Resolver::new(vec![server.local_addr().unwrap()]);
+        let handle = thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (n, addr) = server.recv_from(&mut buf).unwrap();
+            let query = Message::try_from(&buf[..n]).unwrap();
+            assert_eq!(
+                query.questions[0].name.to_string(),
+                "1.2.0.192.in-addr.arpa."
+            );
+            assert_eq!(query.questions[0].r#type, RecordType::PTR);
+            let answers = [RData::PTR("host.example".parse().unwrap())];
+            server
+                .send_to(&reply(&buf[..n], 0, &answers), addr)
+                .unwrap();
+        });
+        let names = resolver.lookup_addr([192, 0, 2, 1].into()).unwrap();
+        assert_eq!(names, ["host.example.".parse::<Name>().unwrap()]);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,653 @@
+use crate::edns::Edns;
+use crate::message::{Flags, Header, Message, Question, Record};
+use crate::name::Name;
+use crate::rdata::{RData, RecordType};
+use crate::zone::Zone;
+use anyhow::Error;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Largest possible UDP payload, so queries are never cut off by our receive buffer.
+const MAX_DGRAM_SIZE: usize = u16::MAX as usize;
+
+// Largest UDP response for clients without EDNS (RFC 1035 section 4.2.1).
+const MAX_PLAIN_UDP_SIZE: usize = 512;
+
+// The UDP payload size we advertise, and the most we send regardless of what clients advertise.
+// See Edns::default.
+const MAX_EDNS_UDP_SIZE: usize = 1232;
+
+// Largest response that fits behind the two byte length prefix of TCP (RFC 1035 section 4.2.2).
+const MAX_TCP_SIZE: usize = u16::MAX as usize;
+
+// How many threads answer UDP queries, and how many serve TCP connections. Queries beyond that
+// wait in the socket buffer, so a flood can't exhaust threads or memory.
+const UDP_WORKERS: usize = 16;
+const TCP_WORKERS: usize = 16;
+
+// How many accepted TCP connections may wait for a worker. Connections beyond that are closed
+// right away.
+const TCP_QUEUE: usize = 64;
+
+// How many CNAMEs within the zone to follow when answering.
+const MAX_CNAMES: usize = 8;
+
+// How long a TCP connection may sit idle between queries (RFC 7766 section 6.2.3), or stall while
+// a query is read or a response written, and how long it may last in all. Together they keep
+// clients that are slow on purpose from holding on to a worker.
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+const TCP_CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+// How long to wait before accepting again after accepting a connection failed, for instance
+// because we ran out of file descriptors. It doubles with every failure in a row.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+// RCODEs the server answers with.
+const FORMERR: u8 = 1;
+const SERVFAIL: u8 = 2;
+const NXDOMAIN: u8 = 3;
+const NOTIMP: u8 = 4;
+const REFUSED: u8 = 5;
+// BADVERS is 16, which takes the extended RCODE bits of the OPT record (RFC 6891 section 6.1.3).
+const BADVERS: u16 = 16;
+
+// Fills in responses to queries. The listening, parsing and error handling around it is shared by
+// every kind of server.
+pub trait Handler: Send + Sync + 'static {
+    // Add the answer to a standard query with a single question to the response, which starts out
+    // as an empty NOERROR response echoing the question. An RCODE too big for the header takes an
+    // OPT record with its extended bits in the additional section; any other OPT record is
+    // replaced by the server's own.
+    fn answer(&self, query: &Message, response: &mut Message);
+}
+
+// Answer queries on the UDP socket and TCP listener until an error occurs. A fixed pool of
+// threads receives and answers UDP queries, and another serves the TCP connections that a thread
+// of their own accepts, so a slow answer only holds up one thread. A TCP connection may carry any
+// number of queries, within TCP_CONNECTION_TIMEOUT.
+pub fn run(handler: impl Handler, udp: UdpSocket, tcp: TcpListener) -> Result<(), Error> {
+    let handler = Arc::new(handler);
+    let (connections, queue) = mpsc::sync_channel(TCP_QUEUE);
+    let queue = Arc::new(Mutex::new(queue));
+    for _ in 0..TCP_WORKERS {
+        let (handler, queue) = (handler.clone(), queue.clone());
+        thread::spawn(move || loop {
+            let Ok(connection) = queue.lock().unwrap().recv() else {
+                return;
+            };
+            // A broken connection only ends that connection.
+            let _ = serve_tcp(&*handler, connection);
+        });
+    }
+    thread::spawn(move || accept(&tcp, &connections));
+
+    let (errors, failed) = mpsc::channel();
+    for _ in 0..UDP_WORKERS {
+        let (handler, udp, errors) = (handler.clone(), udp.try_clone()?, errors.clone());
+        thread::spawn(move || errors.send(serve_udp(&*handler, &udp)));
+    }
+    // The workers only stop when receiving fails.
+    Ok(failed.recv()??)
+}
+
+// Receive and answer UDP queries until receiving fails.
+fn serve_udp(handler: &impl Handler, udp: &UdpSocket) -> io::Result<()> {
+    let mut buf = vec![0; MAX_DGRAM_SIZE];
+    loop {
+        let (n, from) = udp.recv_from(&mut buf)?;
+        if let Some(response) = respond(handler, &buf[..n], true) {
+            // A client that has gone away is no reason to stop serving the others.
+            let _ = udp.send_to(&response, from);
+        }
+    }
+}
+
+// Accept TCP connections and queue them for the workers, closing any the queue has no room for.
+fn accept(tcp: &TcpListener, connections: &mpsc::SyncSender<Connection>) {
+    let mut backoff = ACCEPT_BACKOFF;
+    loop {
+        let stream = match tcp.accept() {
+            Ok((stream, _)) => stream,
+            Err(_) => {
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                continue;
+            }
+        };
+        backoff = ACCEPT_BACKOFF;
+        let connection = Connection {
+            stream,
+            deadline: Instant::now() + TCP_CONNECTION_TIMEOUT,
+        };
+        match connections.try_send(connection) {
+            Ok(()) | Err(TrySendError::Full(_)) => {}
+            Err(TrySendError::Disconnected(_)) => return,
+        }
+    }
+}
+
+// A TCP connection whose reads and writes fail once they stall for TCP_IDLE_TIMEOUT, or once the
+// connection has run past its deadline.
+struct Connection {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Connection {
+    // How long the next read or write may take.
+    fn timeout(&self) -> io::Result<Duration> {
+        self.deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .map(|remaining| remaining.min(TCP_IDLE_TIMEOUT))
+            .ok_or(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "connection is past its deadline",
+            ))
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.set_read_timeout(Some(self.timeout()?))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.set_write_timeout(Some(self.timeout()?))?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+// Answer length-prefixed queries until the client closes the connection, goes quiet, or runs out
+// of time.
+fn serve_tcp(handler: &impl Handler, mut connection: Connection) -> io::Result<()> {
+    loop {
+        let mut length = [0; 2];
+        connection.read_exact(&mut length)?;
+        let mut request = vec![0; u16::from_be_bytes(length) as usize];
+        connection.read_exact(&mut request)?;
+        let Some(response) = respond(handler, &request, false) else {
+            return Ok(());
+        };
+        let length = u16::try_from(response.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "response is too long"))?;
+        let mut framed = length.to_be_bytes().to_vec();
+        framed.extend(response);
+        connection.write_all(&framed)?;
+    }
+}
+
+// Build the response to a query in wire format. Returns None for messages that don't deserve one:
+// responses, and queries too short to have a header. Queries the handler can't answer get
+// FORMERR, NOTIMP or BADVERS. UDP responses that don't fit in the client's payload size are
+// truncated, telling the client to retry over TCP. So are TCP responses too long for the length
+// prefix, which leaves the client nowhere to retry, but is better than a corrupt stream.
+pub fn respond(handler: &impl Handler, request: &[u8], udp: bool) -> Option<Vec<u8>> {
+    let query = match Message::try_from(request) {
+        Ok(query) if query.header.flags.qr => return None,
+        Ok(query) => query,
+        // Echo the header of a query we can't parse, so the client can match the error to it.
+        Err(_) if request.len() >= 12 && request[2] & 0x80 == 0 => {
+            let flags = Flags::from(u16::from_be_bytes([request[2], request[3]]));
+            let id = u16::from_be_bytes([request[0], request[1]]);
+            let mut response = response_to(id, flags, Vec::new());
+            response.header.flags.rcode = FORMERR;
+            return response.encode().ok();
+        }
+        Err(_) => return None,
+    };
+    let edns = query.edns();
+    let mut response = response_to(query.header.id, query.header.flags, query.questions.clone());
+
+    // Clients using a newer EDNS version than ours get BADVERS and nothing else (RFC 6891
+    // section 6.1.3).
+    let badvers = edns.as_ref().is_some_and(|edns| edns.version > 0);
+    if !badvers {
+        if query.header.flags.opcode != 0 {
+            response.header.flags.rcode = NOTIMP;
+        } else if response.questions.len() != 1 {
+            response.header.flags.rcode = FORMERR;
+        } else {
+            handler.answer(&query, &mut response);
+        }
+    }
+    let extended_rcode = response.edns().map_or(0, |edns| edns.extended_rcode);
+    response.additional.retain(|r| r.r#type != RecordType::OPT);
+    if edns.is_some() {
+        let mut opt = Edns {
+            extended_rcode,
+            ..Edns::default()
+        };
+        if badvers {
+            opt.extended_rcode = (BADVERS >> 4) as u8;
+        }
+        response.additional.push(opt_record(&opt));
+    } else if extended_rcode != 0 {
+        // A client without EDNS can't be given an extended RCODE.
+        response.header.flags.rcode = SERVFAIL;
+    }
+
+    let mut encoded = response.encode().ok()?;
+    let limit = match (udp, edns) {
+        (false, _) => MAX_TCP_SIZE,
+        (true, Some(edns)) => {
+            (edns.payload_size as usize).clamp(MAX_PLAIN_UDP_SIZE, MAX_EDNS_UDP_SIZE)
+        }
+        (true, None) => MAX_PLAIN_UDP_SIZE,
+    };
+    if encoded.len() > limit {
+        response.header.flags.tc = true;
+        response.answers.clear();
+        response.authority.clear();
+        response.additional.retain(|r| r.r#type == RecordType::OPT);
+        encoded = response.encode().ok()?;
+    }
+    Some(encoded)
+}
+
+// An authoritative nameserver for a single zone. Queries for names outside of the zone are
+// refused, and it doesn't recurse.
+pub struct Server {
+    zone: Zone,
+}
+
+impl Server {
+    pub fn new(zone: Zone) -> Self {
+        Server { zone }
+    }
+
+    // Add the addresses of the nameservers and mail exchanges in the response, when the zone has
+    // them, so the client doesn't have to look them up (RFC 1035 section 4.3.2).
+    fn add_addresses(&self, response: &mut Message) {
+        let targets: Vec<Name> = response
+            .answers
+            .iter()
+            .chain(&response.authority)
+            .filter_map(|r| match &r.rdata {
+                RData::NS(name) | RData::MX { exchange: name, .. } => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+        for target in targets {
+            let addresses = self
+                .zone
+                .records(&target)
+                .iter()
+                .filter(|r| matches!(r.r#type, RecordType::A | RecordType::AAAA));
+            for address in addresses {
+                let duplicate = response
+                    .additional
+                    .iter()
+                    .any(|r| r.name == address.name && r.rdata == address.rdata);
+                if !duplicate {
+                    response.additional.push(address.clone());
+                }
+            }
+        }
+    }
+}
+
+impl Handler for Server {
+    // Fill in the answer to the question from the zone:
+    //
+    // - The records of the type at the name, with the addresses of any MX exchanges.
+    // - A CNAME at the name instead, followed by the answer for its target if that's in the zone.
+    // - A referral to the nameservers of a delegated subdomain, with glue addresses.
+    // - NXDOMAIN if the name doesn't exist, or an empty answer if it has no records of the type.
+    //   Either way the SOA record goes in the authority section (RFC 2308 section 3).
+    fn answer(&self, _: &Message, response: &mut Message) {
+        let zone = &self.zone;
+        let question = &response.questions[0];
+        let r#type = question.r#type;
+        let mut name = question.name.clone();
+        if !name.is_subdomain_of(&zone.origin) || question.class != 1 {
+            response.header.flags.rcode = REFUSED;
+            return;
+        }
+
+        response.header.flags.aa = true;
+        for _ in 0..MAX_CNAMES {
+            if let Some(nameservers) = zone.delegation(&name) {
+                // We aren't authoritative for the delegated zone, unless we already answered with
+                // a CNAME from our own.
+                response.header.flags.aa = !response.answers.is_empty();
+                let nameservers = nameservers.iter().filter(|r| r.r#type == RecordType::NS);
+                response.authority.extend(nameservers.cloned());
+                break;
+            }
+            let records = zone.records(&name);
+            let matching: Vec<_> = records
+                .iter()
+                .filter(|r| r.r#type == r#type || r#type == RecordType::ANY)
+                .cloned()
+                .collect();
+            if !matching.is_empty() {
+                response.answers.extend(matching);
+                break;
+            }
+            match records.iter().find(|r| r.r#type == RecordType::CNAME) {
+                Some(
+                    cname @ Record {
+                        rdata: RData::CNAME(target),
+                        ..
+                    },
+                ) => {
+                    response.answers.push(cname.clone());
+                    name = target.clone();
+                    if !name.is_subdomain_of(&zone.origin) {
+                        break;
+                    }
+                }
+                _ => {
+                    if !zone.contains(&name) {
+                        response.header.flags.rcode = NXDOMAIN;
+                    }
+                    response.authority.push(negative_soa(zone.soa()));
+                    break;
+                }
+            }
+        }
+        self.add_addresses(response);
+    }
+}
+
+// An empty response echoing the query's ID, opcode, RD flag and question.
+pub(crate) fn response_to(id: u16, query: Flags, questions: Vec<Question>) -> Message {
+    Message {
+        header: Header {
+            id,
+            flags: Flags {
+                qr: true,
+                opcode: query.opcode,
+                rd: query.rd,
+                ..Flags::default()
+            },
+            qdcount: 0,
+            ancount: 0,
+            nscount: 0,
+            arcount: 0,
+        },
+        questions,
+        answers: Vec::new(),
+        authority: Vec::new(),
+        additional: Vec::new(),
+    }
+}
+
+// The SOA record for a negative response. Its TTL is capped by the MINIMUM field, which is how
+// long resolvers may cache the negative result (RFC 2308 section 3).
+fn negative_soa(soa: &Record) -> Record {
+    let mut soa = soa.clone();
+    if let RData::SOA { minimum, .. } = soa.rdata {
+        soa.ttl = soa.ttl.min(minimum);
+    }
+    soa
+}
+
+// The OPT pseudo-record carrying our EDNS parameters (see the layout in edns.rs).
+pub(crate) fn opt_record(edns: &Edns) -> Record {
+    Record {
+        name: Name::root(),
+        r#type: RecordType::OPT,
+        class: edns.payload_size,
+        ttl: (edns.extended_rcode as u32) << 24 | (edns.version as u32) << 16,
+        rdata: RData::OPT(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::Query;
+    use crate::transport::{self, Options};
+    use quickcheck_macros::quickcheck;
+    use std::net::SocketAddr;
+
+    const ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@           SOA   ns1 hostmaster 1 7200 3600 1209600 300
+            NS    ns1
+            MX    10 mail
+ns1         A     192.0.2.1
+mail        A     192.0.2.25
+www         A     192.0.2.80
+            AAAA  2001:db8::80
+alias       CNAME www
+outside     CNAME www.example.net.
+a.b         TXT   \"empty non-terminal above\"
+sub         NS    ns.sub
+ns.sub      A     192.0.2.53
+";
+
+    // Serve the test zone on ephemeral UDP and TCP ports.
+    fn serve() -> (SocketAddr, SocketAddr) {
+        let zone = Zone::parse(ZONE, Name::root()).unwrap();
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addrs = (udp.local_addr().unwrap(), tcp.local_addr().unwrap());
+        thread::spawn(move || run(Server::new(zone), udp, tcp));
+        addrs
+    }
+
+    fn query(server: SocketAddr, name: &str, r#type: RecordType, tcp: bool) -> Message {
+        let options = Options {
+            timeout: Duration::from_secs(1),
+            retries: 0,
+            tcp,
+            ..Options::default()
+        };
+        transport::exchange(&Query::new(name, r#type), &[server], &options)
+            .unwrap()
+            .0
+    }
+
+    fn section(records: &[Record]) -> Vec<String> {
+        records
+            .iter()
+            .filter(|r| r.r#type != RecordType::OPT)
+            .map(|r| format!("{} {} {}", r.name, r.r#type, r.rdata))
+            .collect()
+    }
+
+    #[test]
+    fn answer_queries() {
+        let (udp, tcp) = serve();
+
+        let response = query(udp, "www.example.com", RecordType::AAAA, false);
+        assert!(response.header.flags.aa);
+        assert_eq!(
+            section(&response.answers),
+            ["www.example.com. AAAA 2001:db8::80"]
+        );
+        // The OPT record is echoed back.
+        assert!(response.edns().is_some());
+
+        let response = query(tcp, "example.com", RecordType::MX, true);
+        assert_eq!(
+            section(&response.answers),
+            ["example.com. MX 10 mail.example.com."]
+        );
+        assert_eq!(
+            section(&response.additional),
+            ["mail.example.com. A 192.0.2.25"]
+        );
+
+        let response = query(udp, "alias.example.com", RecordType::A, false);
+        assert_eq!(
+            section(&response.answers),
+            [
+                "alias.example.com. CNAME www.example.com.",
+                "www.example.com. A 192.0.2.80"
+            ]
+        );
+        let response = query(udp, "outside.example.com", RecordType::A, false);
+        assert_eq!(
+            section(&response.answers),
+            ["outside.example.com. CNAME www.example.net."]
+        );
+    }
+
+    #[test]
+    fn answer_negatively() {
+        let (udp, _) = serve();
+        let soa =
+            "example.com. SOA ns1.example.com. hostmaster.example.com. 1 7200 3600 1209600 300";
+
+        let response = query(udp, "nope.example.com", RecordType::A, false);
+        assert_eq!(response.header.flags.rcode, NXDOMAIN);
+        assert_eq!(section(&response.authority), [soa]);
+        assert_eq!(response.authority[0].ttl, 300);
+
+        // Names with records of other types, and names with only descendants, exist.
+        for name in ["www.example.com", "b.example.com"] {
+            let response = query(udp, name, RecordType::MX, false);
+            assert_eq!(response.header.flags.rcode, 0);
+            assert!(response.answers.is_empty());
+            assert_eq!(section(&response.authority), [soa]);
+        }
+
+        let response = query(udp, "www.example.net", RecordType::A, false);
+        assert_eq!(response.header.flags.rcode, REFUSED);
+        assert!(!response.header.flags.aa);
+    }
+
+    #[test]
+    fn refer_to_delegations() {
+        let (udp, _) = serve();
+        let response = query(udp, "www.sub.example.com", RecordType::A, false);
+        assert!(!response.header.flags.aa);
+        assert!(response.answers.is_empty());
+        assert_eq!(
+            section(&response.authority),
+            ["sub.example.com. NS ns.sub.example.com."]
+        );
+        assert_eq!(
+            section(&response.additional),
+            ["ns.sub.example.com. A 192.0.2.53"]
+        );
+    }
+
+    #[test]
+    fn truncate_udp_responses() {
+        let mut zone = String::from("@ 60 SOA ns hostmaster 1 2 3 4 5\n");
+        for i in 0..40 {
+            zone.push_str(&format!("big A 192.0.2.{i}\n"));
+        }
+        let server = Server::new(Zone::parse(&zone, "example".parse().unwrap()).unwrap());
+        let mut request = Vec::new();
+        Query {
+            edns: None,
+            ..Query::new("big.example", RecordType::A)
+        }
+        .encode(&mut request)
+        .unwrap();
+
+        let udp = Message::try_from(respond(&server, &request, true).unwrap().as_slice()).unwrap();
+        assert!(udp.header.flags.tc);
+        assert!(udp.answers.is_empty());
+        let tcp = Message::try_from(respond(&server, &request, false).unwrap().as_slice()).unwrap();
+        assert!(!tcp.header.flags.tc);
+        assert_eq!(tcp.answers.len(), 40);
+    }
+
+    #[test]
+    fn truncate_huge_tcp_responses() {
+        let mut zone = String::from("@ 60 SOA ns hostmaster 1 2 3 4 5\n");
+        for i in 0..300 {
+            zone.push_str(&format!("big TXT \"{i:0>250}\"\n"));
+        }
+        let server = Server::new(Zone::parse(&zone, "example".parse().unwrap()).unwrap());
+        let mut request = Vec::new();
+        Query::new("big.example", RecordType::TXT)
+            .encode(&mut request)
+            .unwrap();
+        let response = respond(&server, &request, false).unwrap();
+        assert!(response.len() <= MAX_TCP_SIZE);
+        let tcp = Message::try_from(response.as_slice()).unwrap();
+        assert!(tcp.header.flags.tc);
+        assert!(tcp.answers.is_empty());
+    }
+
+    #[test]
+    fn reject_bad_queries() {
+        let server = Server::new(Zone::parse(ZONE, Name::root()).unwrap());
+        // A header claiming a question that isn't there.
+        let request = [0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        let response =
+            Message::try_from(respond(&server, &request, true).unwrap().as_slice()).unwrap();
+        assert_eq!(response.header.id, 0x1234);
+        assert_eq!(response.header.flags.rcode, FORMERR);
+        // Responses and runt packets get no answer at all.
+        let response = [0x12, 0x34, 0x81, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(respond(&server, &response, true).is_none());
+        assert!(respond(&server, &[0x12], true).is_none());
+    }
+
+    #[test]
+    fn close_slow_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let start = Instant::now();
+        let connection = Connection {
+            stream,
+            deadline: start + Duration::from_millis(300),
+        };
+        let server = Server::new(Zone::parse(ZONE, Name::root()).unwrap());
+        let serving = thread::spawn(move || serve_tcp(&server, connection));
+        // A query trickling in a byte at a time never stalls for long, but the deadline still
+        // ends the connection.
+        let mut request = Vec::new();
+        Query::new("www.example.com", RecordType::A)
+            .encode(&mut request)
+            .unwrap();
+        let mut framed = (request.len() as u16).to_be_bytes().to_vec();
+        framed.extend(request);
+        for byte in framed {
+            if serving.is_finished() || client.write_all(&[byte]).is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        let err = serving.join().unwrap().unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    // Whatever a client sends, the server answers or ignores it without panicking.
+    #[quickcheck]
+    fn answer_damaged_queries(name: u8, damage: Vec<(usize, u8)>, length: usize) -> bool {
+        let server = Server::new(Zone::parse(ZONE, Name::root()).unwrap());
+        let names = ["www", "alias", "outside", "a", "a.b", "x.sub", "missing"];
+        let name = format!("{}.example.com", names[name as usize % names.len()]);
+        let mut request = Vec::new();
+        Query::new(name, RecordType::ANY)
+            .encode(&mut request)
+            .unwrap();
+        for (i, byte) in damage {
+            let i = i % request.len();
+            request[i] = byte;
+        }
+        request.truncate(length % (request.len() + 1));
+        if let Some(response) = respond(&server, &request, true) {
+            Message::try_from(response.as_slice()).unwrap();
+        }
+        true
+    }
+}
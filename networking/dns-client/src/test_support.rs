@@ -0,0 +1,39 @@
+// Helpers shared by the tests that run stand-in servers.
+
+use crate::message::{Message, Record};
+use crate::rdata::RData;
+
+// Start a stand-in server's response to an encoded query: the query's ID and question, and the
+// RCODE, with empty sections. Any EDNS options the query carried aren't echoed.
+pub fn response(query: &[u8], rcode: u8) -> Message {
+    let query = Message::try_from(query).unwrap();
+    let mut header = query.header;
+    header.flags.qr = true;
+    header.flags.ra = true;
+    header.flags.rcode = rcode;
+    Message {
+        header,
+        questions: query.questions,
+        answers: Vec::new(),
+        authority: Vec::new(),
+        additional: Vec::new(),
+    }
+}
+
+// Build a stand-in server's encoded response to an encoded query, like response, with an answer
+// with a TTL of 60 for each RDATA, at the question's name and of its type.
+pub fn reply(query: &[u8], rcode: u8, answers: &[RData]) -> Vec<u8> {
+    let mut response = response(query, rcode);
+    let question = &response.questions[0];
+    response.answers = answers
+        .iter()
+        .map(|rdata| Record {
+            name: question.name.clone(),
+            r#type: question.r#type,
+            class: question.class,
+            ttl: 60,
+            rdata: rdata.clone(),
+        })
+        .collect();
+    response.encode().unwrap()
+}
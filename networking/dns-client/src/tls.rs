@@ -0,0 +1,158 @@
+use anyhow::{anyhow, Error};
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Port DNS-over-TLS servers listen on (RFC 7858 section 3.1).
+pub const DOT_PORT: u16 = 853;
+
+// Settings for DNS-over-TLS (RFC 7858). Queries are sent over a TLS connection with the same
+// length-prefixed framing as plain TCP, after checking the server's certificate against a set of
+// trusted roots.
+#[derive(Clone)]
+pub struct Tls {
+    // The name the server's certificate must be valid for. When None, the certificate must be
+    // valid for the server's IP address instead.
+    pub server_name: Option<String>,
+    config: Arc<ClientConfig>,
+}
+
+impl Tls {
+    // Trust the Mozilla root certificates, like a web browser.
+    pub fn new() -> Result<Self, Error> {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        Tls::with_roots(roots)
+    }
+
+    // Trust only the given DER encoded certificates, such as a server's self-signed certificate.
+    pub fn with_certificates(certificates: Vec<CertificateDer<'static>>) -> Result<Self, Error> {
+        let mut roots = RootCertStore::empty();
+        for certificate in certificates {
+            roots.add(certificate)?;
+        }
+        Tls::with_roots(roots)
+    }
+
+    fn with_roots(roots: RootCertStore) -> Result<Self, Error> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Tls {
+            server_name: None,
+            config: Arc::new(config),
+        })
+    }
+
+    // Open a TLS connection to the server. The handshake itself happens on the first read or write.
+    pub(crate) fn connect(
+        &self,
+        server: SocketAddr,
+        timeout: Duration,
+    ) -> Result<StreamOwned<ClientConnection, TcpStream>, Error> {
+        let name = match &self.server_name {
+            Some(name) => ServerName::try_from(name.clone())
+                .map_err(|_| anyhow!("invalid TLS server name {name}"))?,
+            None => ServerName::from(server.ip()),
+        };
+        let connection = ClientConnection::new(self.config.clone(), name)?;
+        let stream = TcpStream::connect_timeout(&server, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(StreamOwned::new(connection, stream))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::query::Query;
+    use crate::rdata::{RData, RecordType};
+    use crate::test_support::reply;
+    use crate::transport::{self, Options};
+    use rustls::pki_types::PrivateKeyDer;
+    use rustls::{ServerConfig, ServerConnection};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // A TLS server configuration with a self-signed certificate for the given names, for
+    // stand-in servers. Returns the certificate along with it, for clients to trust.
+    pub(crate) fn server_config(names: &[&str]) -> (Arc<ServerConfig>, CertificateDer<'static>) {
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        let key = rcgen::generate_simple_self_signed(names).unwrap();
+        let certificate = CertificateDer::from(key.cert);
+        let private_key = PrivateKeyDer::try_from(key.key_pair.serialize_der()).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![certificate.clone()], private_key)
+            .unwrap();
+        (Arc::new(config), certificate)
+    }
+
+    // Run a DNS-over-TLS stand-in with a self-signed certificate for the given names, which
+    // answers one query with an A record. Returns its address and certificate.
+    fn serve(names: &[&str]) -> (SocketAddr, CertificateDer<'static>) {
+        let (config, certificate) = server_config(names);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let connection = ServerConnection::new(config).unwrap();
+            let mut stream = StreamOwned::new(connection, stream);
+            let mut length = [0; 2];
+            let Ok(()) = stream.read_exact(&mut length) else {
+                return;
+            };
+            let mut buf = vec![0; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut buf).unwrap();
+            let response = reply(&buf, 0, &[RData::A([192, 0, 2, 1].into())]);
+            stream
+                .write_all(&(response.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&response).unwrap();
+            stream.flush().unwrap();
+        });
+        (server, certificate)
+    }
+
+    fn options(tls: Tls) -> Options {
+        Options {
+            timeout: Duration::from_secs(1),
+            retries: 0,
+            tls: Some(tls),
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn query_over_tls() {
+        let (server, certificate) = serve(&["dns.test"]);
+        let mut tls = Tls::with_certificates(vec![certificate]).unwrap();
+        tls.server_name = Some("dns.test".to_string());
+        let query = Query {
+            edns: None,
+            ..Query::new("example.com", RecordType::A)
+        };
+        let (response, _) = transport::exchange(&query, &[server], &options(tls)).unwrap();
+        assert_eq!(response.answers[0].rdata.to_string(), "192.0.2.1");
+    }
+
+    #[test]
+    fn reject_wrong_server_name() {
+        // Without a server name, the certificate must be valid for the server's address.
+        let (server, certificate) = serve(&["dns.test"]);
+        let tls = Tls::with_certificates(vec![certificate]).unwrap();
+        let query = Query::new("example.com", RecordType::A);
+        let err = transport::exchange(&query, &[server], &options(tls)).unwrap_err();
+        assert!(err.to_string().contains("certificate"), "{err}");
+    }
+}
@@ -0,0 +1,354 @@
+use crate::message::Message;
+use crate::name::Name;
+use crate::output::Presentation;
+use crate::query::Query;
+use crate::rdata::{RData, RecordType};
+use crate::transport::{self, Options};
+use anyhow::{bail, Error};
+use std::fmt::{self, Display};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+// IPv4 addresses of the root servers a.root-servers.net through m.root-servers.net.
+const ROOT_HINTS: [Ipv4Addr; 13] = [
+    Ipv4Addr::new(198, 41, 0, 4),
+    Ipv4Addr::new(170, 247, 170, 2),
+    Ipv4Addr::new(192, 33, 4, 12),
+    Ipv4Addr::new(199, 7, 91, 13),
+    Ipv4Addr::new(192, 203, 230, 10),
+    Ipv4Addr::new(192, 5, 5, 241),
+    Ipv4Addr::new(192, 112, 36, 4),
+    Ipv4Addr::new(198, 97, 190, 53),
+    Ipv4Addr::new(192, 36, 148, 17),
+    Ipv4Addr::new(192, 58, 128, 30),
+    Ipv4Addr::new(193, 0, 14, 129),
+    Ipv4Addr::new(199, 7, 83, 42),
+    Ipv4Addr::new(202, 12, 27, 33),
+];
+
+// Upper bound on the queries sent for a single lookup, including CNAME restarts and lookups of
+// nameserver addresses that were missing glue. This stops referral loops between servers.
+const MAX_QUERIES: usize = 64;
+
+// How many CNAMEs to follow before giving up.
+const MAX_CNAMES: usize = 8;
+
+// How deep lookups of nameserver addresses may nest.
+const MAX_DEPTH: usize = 4;
+
+pub fn root_hints() -> Vec<SocketAddr> {
+    ROOT_HINTS
+        .iter()
+        .map(|&ip| SocketAddr::new(ip.into(), 53))
+        .collect()
+}
+
+// A response received while walking down from the root.
+pub struct Hop {
+    pub server: SocketAddr,
+    pub elapsed: Duration,
+    pub response: Message,
+}
+
+// Display the records of a hop followed by where they came from, like `dig +trace`.
+impl Display for Hop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for record in self.response.answers.iter().chain(&self.response.authority) {
+            writeln!(f, "{}", Presentation(record))?;
+        }
+        write!(
+            f,
+            ";; Received response from {}#{} in {} ms",
+            self.server.ip(),
+            self.server.port(),
+            self.elapsed.as_millis()
+        )
+    }
+}
+
+// Resolve a name without relying on a recursive resolver: start at the root servers and follow
+// NS referrals (using glue records when present) down to an authoritative server. Each response
+// is passed to on_hop as it arrives. Referred nameservers are contacted on the same port as the
+// server that referred us, so a set of local stand-in servers can be traced as well.
+pub fn trace(
+    domain: &str,
+    r#type: RecordType,
+    roots: &[SocketAddr],
+    options: &Options,
+    on_hop: impl FnMut(&Hop),
+) -> Result<Message, Error> {
+    trace_via(domain, r#type, roots, options, &SocketAddr::new, on_hop)
+}
+
+// Like trace, but locate says where to reach each nameserver given its address and the port of
+// the server that referred us.
+fn trace_via(
+    domain: &str,
+    r#type: RecordType,
+    roots: &[SocketAddr],
+    options: &Options,
+    locate: &dyn Fn(IpAddr, u16) -> SocketAddr,
+    on_hop: impl FnMut(&Hop),
+) -> Result<Message, Error> {
+    let mut tracer = Tracer {
+        roots,
+        options,
+        locate,
+        on_hop,
+        queries: 0,
+    };
+    tracer.resolve(&domain.parse()?, r#type, 0)
+}
+
+struct Tracer<'a, F> {
+    roots: &'a [SocketAddr],
+    options: &'a Options,
+    locate: &'a dyn Fn(IpAddr, u16) -> SocketAddr,
+    on_hop: F,
+    queries: usize,
+}
+
+impl<F: FnMut(&Hop)> Tracer<'_, F> {
+    // Only hops of the top level lookup (depth 0) are reported.
+    fn resolve(&mut self, name: &Name, r#type: RecordType, depth: usize) -> Result<Message, Error> {
+        let mut name = name.clone();
+        let mut zone = Name::root();
+        let mut servers = self.roots.to_vec();
+        let mut cnames = 0;
+        loop {
+            self.queries += 1;
+            if self.queries > MAX_QUERIES {
+                bail!("gave up resolving {name} after {MAX_QUERIES} queries");
+            }
+            let query = Query {
+                recursion_desired: false,
+                ..Query::new(name.to_string(), r#type)
+            };
+            let start = Instant::now();
+            let (response, server) = transport::exchange(&query, &servers, self.options)?;
+            let hop = Hop {
+                server,
+                elapsed: start.elapsed(),
+                response,
+            };
+            if depth == 0 {
+                (self.on_hop)(&hop);
+            }
+            let response = hop.response;
+            response.check_rcode()?;
+
+            // An answer. Follow any CNAMEs in it, and start over from the root if the server
+            // didn't also include the records of the final target.
+            if !response.answers.is_empty() {
+                let target = follow_cnames(&response, &name);
+                if r#type == RecordType::CNAME
+                    || target == name
+                    || response
+                        .answers
+                        .iter()
+                        .any(|r| r.name == target && r.r#type == r#type)
+                {
+                    return Ok(response);
+                }
+                cnames += 1;
+                if cnames > MAX_CNAMES {
+                    bail!("too many CNAMEs resolving {name}");
+                }
+                name = target;
+                zone = Name::root();
+                servers = self.roots.to_vec();
+                continue;
+            }
+
+            // No answer and no referral means the name exists but has no records of this type.
+            let Some((child, nameservers)) = referral(&response, &name, &zone) else {
+                return Ok(response);
+            };
+            let mut addresses = glue(&response, &nameservers);
+            if addresses.is_empty() && depth < MAX_DEPTH {
+                for ns in &nameservers {
+                    let Ok(ns_response) = self.resolve(ns, RecordType::A, depth + 1) else {
+                        continue;
+                    };
+                    addresses = nameserver_addresses(&ns_response, ns);
+                    if !addresses.is_empty() {
+                        break;
+                    }
+                }
+            }
+            servers = addresses
+                .into_iter()
+                .map(|ip| (self.locate)(ip, server.port()))
+                .collect();
+            if servers.is_empty() {
+                bail!("could not find the address of any nameserver for {child}");
+            }
+            zone = child;
+        }
+    }
+}
+
+// Follow the chain of CNAME records in the answer section starting at name, returning the final
+// target (or name itself if it has no CNAME).
+fn follow_cnames(response: &Message, name: &Name) -> Name {
+    let mut target = name.clone();
+    for _ in 0..=MAX_CNAMES {
+        let next = response.answers.iter().find_map(|r| match &r.rdata {
+            RData::CNAME(next) if r.name == target => Some(next.clone()),
+            _ => None,
+        });
+        match next {
+            Some(next) => target = next,
+            None => break,
+        }
+    }
+    target
+}
+
+// Find a delegation in the authority section: NS records for a zone that contains the name and is
+// below the zone we are currently asking. Anything else could send us sideways or back up the
+// tree.
+fn referral(response: &Message, name: &Name, zone: &Name) -> Option<(Name, Vec<Name>)> {
+    let child = response
+        .authority
+        .iter()
+        .filter(|r| r.r#type == RecordType::NS)
+        .map(|r| &r.name)
+        .find(|child| name.is_subdomain_of(child) && child.is_subdomain_of(zone) && *child != zone)?
+        .clone();
+    let nameservers = response
+        .authority
+        .iter()
+        .filter(|r| r.name == child)
+        .filter_map(|r| match &r.rdata {
+            RData::NS(ns) => Some(ns.clone()),
+            _ => None,
+        })
+        .collect();
+    Some((child, nameservers))
+}
+
+// Addresses of the nameservers from glue records in the additional section, IPv4 first.
+fn glue(response: &Message, nameservers: &[Name]) -> Vec<IpAddr> {
+    let mut addresses: Vec<_> = nameservers
+        .iter()
+        .flat_map(|ns| {
+            response
+                .additional
+                .iter()
+                .filter(move |r| r.name == *ns)
+                .filter_map(|r| match r.rdata {
+                    RData::A(ip) => Some(IpAddr::V4(ip)),
+                    RData::AAAA(ip) => Some(IpAddr::V6(ip)),
+                    _ => None,
+                })
+        })
+        .collect();
+    addresses.sort_by_key(|ip| ip.is_ipv6());
+    addresses
+}
+
+// Addresses of a nameserver from the answer to its own A lookup.
+fn nameserver_addresses(response: &Message, ns: &Name) -> Vec<IpAddr> {
+    let target = follow_cnames(response, ns);
+    response
+        .answers
+        .iter()
+        .filter(|r| r.name == target)
+        .filter_map(|r| match r.rdata {
+            RData::A(ip) => Some(IpAddr::V4(ip)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::response;
+    use crate::zone::parse_record;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    // A record to put in a stand-in server's response: its section (0 = answer, 1 = authority,
+    // 2 = additional), and the record in master file format.
+    type StandIn = (u8, &'static str);
+
+    // Run an authoritative stand-in server that answers every query using the handler.
+    fn serve(socket: UdpSocket, handler: fn(&str) -> Vec<StandIn>) {
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok((n, addr)) = socket.recv_from(&mut buf) {
+                let mut response = response(&buf[..n], 0);
+                let query = Message::try_from(&buf[..n]).unwrap();
+                assert!(!query.header.flags.rd);
+                response.header.flags.aa = true;
+                response.header.flags.ra = false;
+                let name = query.questions[0].name.to_string();
+                for (section, record) in handler(&name) {
+                    let record = parse_record(record, &Name::root(), None).unwrap();
+                    match section {
+                        0 => response.answers.push(record),
+                        1 => response.authority.push(record),
+                        _ => response.additional.push(record),
+                    }
+                }
+                socket.send_to(&response.encode().unwrap(), addr).unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn follow_referrals_and_cnames() {
+        // Every server listens on 127.0.0.1 on its own port. The glue points at documentation
+        // addresses, which are mapped to those ports.
+        let root = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let com = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let example = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let roots = [root.local_addr().unwrap()];
+        let servers = [
+            roots[0],
+            com.local_addr().unwrap(),
+            example.local_addr().unwrap(),
+        ];
+        let locate = |ip: IpAddr, _| match ip {
+            IpAddr::V4(ip) if ip == Ipv4Addr::new(192, 0, 2, 53) => servers[1],
+            IpAddr::V4(ip) if ip == Ipv4Addr::new(198, 51, 100, 53) => servers[2],
+            _ => panic!("unexpected nameserver {ip}"),
+        };
+
+        serve(root, |_| {
+            vec![
+                (1, "com. 3600 IN NS a.gtld.test."),
+                (2, "a.gtld.test. 3600 IN A 192.0.2.53"),
+            ]
+        });
+        serve(com, |_| {
+            vec![
+                (1, "example.com. 3600 IN NS ns.example.com."),
+                (2, "ns.example.com. 3600 IN A 198.51.100.53"),
+            ]
+        });
+        serve(example, |name| match name {
+            "www.example.com." => vec![(0, "www.example.com. 3600 IN CNAME web.example.com.")],
+            _ => vec![(0, "web.example.com. 3600 IN A 192.0.2.1")],
+        });
+
+        let mut hops = Vec::new();
+        let options = Options {
+            timeout: Duration::from_secs(1),
+            ..Options::default()
+        };
+        let response = trace_via(
+            "www.example.com",
+            RecordType::A,
+            &roots,
+            &options,
+            &locate,
+            |hop| hops.push(hop.server),
+        )
+        .unwrap();
+        assert_eq!(hops, [servers, servers].concat());
+        assert_eq!(response.answers[0].rdata.to_string(), "192.0.2.1");
+    }
+}
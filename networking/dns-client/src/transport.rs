@@ -0,0 +1,302 @@
+use crate::doh::Doh;
+use crate::message::Message;
+use crate::query::Query;
+use crate::tls::Tls;
+use anyhow::{anyhow, Error};
+use std::io::{self, Cursor, Read, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Largest possible UDP payload, so responses are never cut off by our receive buffer.
+const MAX_DGRAM_SIZE: usize = u16::MAX as usize;
+
+// Delay before the first retry round. It doubles after every round that gets no response, up to
+// MAX_BACKOFF.
+const BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(4);
+
+// Settings for sending a query to the configured nameservers.
+pub struct Options {
+    // How long to wait for a response from a single nameserver.
+    pub timeout: Duration,
+    // How many more times to try every nameserver after the first round fails.
+    pub retries: u32,
+    // Always use TCP instead of only falling back to it for truncated responses.
+    pub tcp: bool,
+    // Send every query over TLS instead of UDP or TCP.
+    pub tls: Option<Tls>,
+    // Send every query over HTTPS to a DoH endpoint. The nameservers are then the addresses of
+    // the endpoint.
+    pub doh: Option<Doh>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            timeout: Duration::from_secs(5),
+            retries: 2,
+            tcp: false,
+            tls: None,
+            doh: None,
+        }
+    }
+}
+
+// Send the query to each nameserver in turn until one of them answers. If a whole round fails,
+// wait with exponential backoff and start over from the first nameserver. Returns the response
+// along with the nameserver that sent it.
+pub fn exchange<T: AsRef<str>>(
+    query: &Query<T>,
+    servers: &[SocketAddr],
+    options: &Options,
+) -> Result<(Message, SocketAddr), Error> {
+    let mut buf = Cursor::new(Vec::new());
+    query.encode(&mut buf)?;
+    let request = buf.into_inner();
+
+    let mut errors = Vec::new();
+    for round in 0..=options.retries {
+        thread::sleep(backoff(round));
+        for &server in servers {
+            match attempt(query, &request, server, options) {
+                Ok(response) => return Ok((response, server)),
+                Err(err) => errors.push(format!("{server}: {err}")),
+            }
+        }
+    }
+    Err(anyhow!(
+        "no response after {} attempts:\n  {}",
+        errors.len(),
+        errors.join("\n  ")
+    ))
+}
+
+// How long to wait before a retry round. The first round starts right away.
+pub(crate) fn backoff(round: u32) -> Duration {
+    match round {
+        0 => Duration::ZERO,
+        round => BACKOFF
+            .saturating_mul(2u32.saturating_pow(round - 1))
+            .min(MAX_BACKOFF),
+    }
+}
+
+// Query a single nameserver. UDP is used unless TCP, TLS or HTTPS is forced, and a truncated UDP
+// response (TC bit set) is retried over TCP with the same server.
+fn attempt<T: AsRef<str>>(
+    query: &Query<T>,
+    request: &[u8],
+    server: SocketAddr,
+    options: &Options,
+) -> Result<Message, Error> {
+    if let Some(doh) = &options.doh {
+        let body = doh.exchange(request, server, options.timeout)?;
+        let response = Message::try_from(body.as_slice())?;
+        query.validate(&response)?;
+        return Ok(response);
+    }
+    if let Some(tls) = &options.tls {
+        let mut stream = tls.connect(server, options.timeout)?;
+        return framed(query, request, &mut stream);
+    }
+    if !options.tcp {
+        let response = udp(query, request, server, options.timeout)?;
+        if !response.header.flags.tc {
+            return Ok(response);
+        }
+    }
+    tcp(query, request, server, options.timeout)
+}
+
+// Perform a single UDP exchange with a nameserver. Responses that don't belong to the query are
+// discarded, and we keep listening until the timeout expires.
+fn udp<T: AsRef<str>>(
+    query: &Query<T>,
+    request: &[u8],
+    server: SocketAddr,
+    timeout: Duration,
+) -> Result<Message, Error> {
+    // Bind to an ephemeral port on the unspecified address of the same family as the nameserver.
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(server)?;
+    socket.send(request)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut buf = vec![0; MAX_DGRAM_SIZE];
+    loop {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|d| !d.is_zero())
+            .ok_or(anyhow!("timed out after {timeout:?}"))?;
+        socket.set_read_timeout(Some(remaining))?;
+        let n = match socket.recv(&mut buf) {
+            Ok(n) => n,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if let Ok(response) = Message::try_from(&buf[..n]) {
+            if query.validate(&response).is_ok() {
+                return Ok(response);
+            }
+        }
+    }
+}
+
+// Perform a single TCP exchange with a nameserver. Messages sent over TCP are prefixed with their
+// length as a two byte integer (RFC 1035 section 4.2.2):
+//
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// |                    LENGTH                     |
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+// /                    MESSAGE                    /
+// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+fn tcp<T: AsRef<str>>(
+    query: &Query<T>,
+    request: &[u8],
+    server: SocketAddr,
+    timeout: Duration,
+) -> Result<Message, Error> {
+    let mut stream = TcpStream::connect_timeout(&server, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    framed(query, request, &mut stream)
+}
+
+// Exchange length-prefixed messages over a connected stream, which is either a TCP connection or
+// a TLS session on top of one.
+fn framed<T: AsRef<str>>(
+    query: &Query<T>,
+    request: &[u8],
+    stream: &mut (impl Read + Write),
+) -> Result<Message, Error> {
+    let length = u16::try_from(request.len())?;
+    let mut framed = Vec::with_capacity(request.len() + 2);
+    framed.extend(length.to_be_bytes());
+    framed.extend(request);
+    stream.write_all(&framed)?;
+    stream.flush()?;
+
+    let mut length = [0; 2];
+    stream.read_exact(&mut length)?;
+    let mut buf = vec![0; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut buf)?;
+
+    let response = Message::try_from(buf.as_slice())?;
+    query.validate(&response)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rdata::{RData, RecordType};
+    use crate::test_support::{reply, response};
+    use std::net::TcpListener;
+
+    #[test]
+    fn limit_backoff() {
+        assert_eq!(backoff(0), Duration::ZERO);
+        assert_eq!(backoff(1), BACKOFF);
+        assert_eq!(backoff(2), BACKOFF * 2);
+        assert_eq!(backoff(20), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn rotate_servers_until_timeout() {
+        // Two nameservers that never respond.
+        let silent = [
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+        ];
+        let servers: Vec<_> = silent.iter().map(|s| s.local_addr().unwrap()).collect();
+        let options = Options {
+            timeout: Duration::from_millis(50),
+            retries: 1,
+            ..Options::default()
+        };
+        let query = Query::new("example.com", RecordType::A);
+        let err = exchange(&query, &servers, &options)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("after 4 attempts"), "{err}");
+        for server in &servers {
+            assert!(err.contains(&server.to_string()), "{err}");
+        }
+        // Every server saw one query per round.
+        let mut buf = [0; 512];
+        for socket in &silent {
+            socket.set_nonblocking(true).unwrap();
+            assert!(socket.recv(&mut buf).is_ok());
+            assert!(socket.recv(&mut buf).is_ok());
+            assert!(socket.recv(&mut buf).is_err());
+        }
+    }
+
+    #[test]
+    fn fall_back_to_next_server() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let servers = [silent.local_addr().unwrap(), server.local_addr().unwrap()];
+        let responder = thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (n, addr) = server.recv_from(&mut buf).unwrap();
+            server.send_to(&reply(&buf[..n], 0, &[]), addr).unwrap();
+        });
+        let options = Options {
+            timeout: Duration::from_millis(100),
+            retries: 0,
+            ..Options::default()
+        };
+        let query = Query::new("example.com", RecordType::A);
+        let (_, from) = exchange(&query, &servers, &options).unwrap();
+        assert_eq!(from, servers[1]);
+        responder.join().unwrap();
+    }
+
+    #[test]
+    fn retry_truncated_over_tcp() {
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(server).unwrap();
+        let responder = thread::spawn(move || {
+            // Answer over UDP with the TC bit set.
+            let mut buf = [0; 512];
+            let (n, addr) = udp.recv_from(&mut buf).unwrap();
+            let mut truncated = response(&buf[..n], 0);
+            truncated.header.flags.tc = true;
+            udp.send_to(&truncated.encode().unwrap(), addr).unwrap();
+
+            // Answer the same query over TCP with an A record.
+            let (mut stream, _) = tcp.accept().unwrap();
+            let mut length = [0; 2];
+            stream.read_exact(&mut length).unwrap();
+            let mut buf = vec![0; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut buf).unwrap();
+            let answer = reply(&buf, 0, &[RData::A([127, 0, 0, 1].into())]);
+            stream
+                .write_all(&(answer.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&answer).unwrap();
+        });
+        let query = Query {
+            edns: None,
+            ..Query::new("example.com", RecordType::A)
+        };
+        let (response, _) = exchange(&query, &[server], &Options::default()).unwrap();
+        assert_eq!(response.answers[0].rdata.to_string(), "127.0.0.1");
+        responder.join().unwrap();
+    }
+}
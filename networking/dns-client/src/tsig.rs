@@ -0,0 +1,292 @@
+use crate::message::{Reader, Record};
+use crate::name::Name;
+use crate::rdata::{RData, RecordType};
+use anyhow::{anyhow, bail, Error};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::hmac;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// HMAC-SHA256 is the only algorithm we sign with (RFC 8945 section 6).
+const HMAC_SHA256: &str = "hmac-sha256";
+
+// How far apart our clock and the other side's may be, in seconds. This is the value RFC 8945
+// section 10 recommends.
+const FUDGE: u16 = 300;
+
+// TSIG records always have class ANY and a TTL of 0.
+const ANY: u16 = 255;
+
+// Errors the other side puts in the TSIG record when it can't verify ours (RFC 8945 section 3).
+const BADSIG: u16 = 16;
+const BADKEY: u16 = 17;
+const BADTIME: u16 = 18;
+const BADTRUNC: u16 = 22;
+
+// A shared secret for signing messages with TSIG (RFC 8945). Both sides need the same key name
+// and secret, as configured with a key statement in BIND:
+//
+//     key "update-key." {
+//         algorithm hmac-sha256;
+//         secret "c2VjcmV0IHNoYXJlZCB3aXRoIHRoZSBzZXJ2ZXI=";
+//     };
+#[derive(Clone)]
+pub struct Key {
+    pub name: Name,
+    secret: Vec<u8>,
+}
+
+// Parse a key in the form nsupdate -y takes: [hmac-sha256:]name:secret, with the secret in
+// base64.
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split(':').collect();
+        let (name, secret) = match fields.as_slice() {
+            [name, secret] => (name, secret),
+            [algorithm, name, secret] if algorithm.eq_ignore_ascii_case(HMAC_SHA256) => {
+                (name, secret)
+            }
+            [algorithm, _, _] => bail!("unsupported TSIG algorithm {algorithm}"),
+            _ => bail!("TSIG keys must be given as [hmac-sha256:]name:secret"),
+        };
+        let secret = STANDARD
+            .decode(secret)
+            .map_err(|err| anyhow!("invalid TSIG secret: {err}"))?;
+        Ok(Key::new(name.parse()?, secret))
+    }
+}
+
+impl Key {
+    pub fn new(name: Name, secret: Vec<u8>) -> Self {
+        Key { name, secret }
+    }
+
+    // Sign an encoded message by appending a TSIG record to it. A response is signed along with
+    // the MAC of the request it answers, which ties the two together. Returns the new MAC.
+    pub fn sign(
+        &self,
+        message: &mut Vec<u8>,
+        request_mac: Option<&[u8]>,
+    ) -> Result<Vec<u8>, Error> {
+        if message.len() < 12 {
+            bail!("message is too short to sign");
+        }
+        let arcount = u16::from_be_bytes([message[10], message[11]])
+            .checked_add(1)
+            .ok_or(anyhow!("message has too many additional records to sign"))?;
+        let mut tsig = RData::TSIG {
+            algorithm: HMAC_SHA256.parse()?,
+            time_signed: now()?,
+            fudge: FUDGE,
+            mac: Vec::new(),
+            original_id: u16::from_be_bytes([message[0], message[1]]),
+            error: 0,
+            other: Vec::new(),
+        };
+        let data = self.signed_data(message, &tsig, request_mac)?;
+        let mac = hmac::sign(&self.hmac_key(), &data).as_ref().to_vec();
+        if let RData::TSIG { mac: field, .. } = &mut tsig {
+            field.clone_from(&mac);
+        }
+
+        // The owner and algorithm names are never compressed.
+        self.name.encode(message);
+        message.extend(u16::from(RecordType::TSIG).to_be_bytes());
+        message.extend(ANY.to_be_bytes());
+        message.extend(0u32.to_be_bytes());
+        let mut rdata = Vec::new();
+        tsig.encode(&mut rdata)?;
+        message.extend(u16::try_from(rdata.len())?.to_be_bytes());
+        message.extend(rdata);
+        message[10..12].copy_from_slice(&arcount.to_be_bytes());
+        Ok(mac)
+    }
+
+    // Check the TSIG record at the end of an encoded message, which must be the last record of
+    // the additional section. Returns the MAC, for signing the response to a request.
+    pub fn verify(&self, message: &[u8], request_mac: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+        let (start, record) = last_record(message)?;
+        let RData::TSIG {
+            algorithm,
+            time_signed,
+            fudge,
+            mac,
+            error,
+            ..
+        } = &record.rdata
+        else {
+            bail!("the message is not signed");
+        };
+        if record.name.to_lowercase() != self.name.to_lowercase() {
+            bail!("the message is signed with the key {}", record.name);
+        }
+        if algorithm.to_lowercase() != HMAC_SHA256.parse()? {
+            bail!("unsupported TSIG algorithm {algorithm}");
+        }
+        match *error {
+            0 => {}
+            BADSIG => bail!("BADSIG: the signature was rejected"),
+            BADKEY => bail!("BADKEY: the key {} is not known", self.name),
+            BADTIME => bail!("BADTIME: the clocks are more than {fudge} seconds apart"),
+            BADTRUNC => bail!("BADTRUNC: the MAC was truncated"),
+            error => bail!("TSIG error {error}"),
+        }
+
+        // The MAC covers the message as it was before the TSIG record was added.
+        let mut unsigned = message[..start].to_vec();
+        let arcount = u16::from_be_bytes([unsigned[10], unsigned[11]]) - 1;
+        unsigned[10..12].copy_from_slice(&arcount.to_be_bytes());
+        if let RData::TSIG { original_id, .. } = record.rdata {
+            unsigned[..2].copy_from_slice(&original_id.to_be_bytes());
+        }
+        let data = self.signed_data(&unsigned, &record.rdata, request_mac)?;
+        if hmac::verify(&self.hmac_key(), &data, mac).is_err() {
+            bail!("the TSIG signature does not match");
+        }
+        let now = now()?;
+        if now.abs_diff(*time_signed) > u64::from(*fudge) {
+            bail!("the message was signed at {time_signed}, more than {fudge} seconds from now");
+        }
+        Ok(mac.clone())
+    }
+
+    // The data the MAC covers: the message without its TSIG record, followed by the TSIG
+    // variables (RFC 8945 section 4.3.3):
+    //
+    //     [request MAC size, request MAC] message key-name class TTL algorithm
+    //     time-signed fudge error other-len other-data
+    //
+    // Names are in canonical form: lowercase and uncompressed.
+    fn signed_data(
+        &self,
+        message: &[u8],
+        tsig: &RData,
+        request_mac: Option<&[u8]>,
+    ) -> Result<Vec<u8>, Error> {
+        let RData::TSIG {
+            algorithm,
+            time_signed,
+            fudge,
+            error,
+            other,
+            ..
+        } = tsig
+        else {
+            bail!("not a TSIG record");
+        };
+        let mut data = Vec::new();
+        if let Some(request_mac) = request_mac {
+            data.extend(u16::try_from(request_mac.len())?.to_be_bytes());
+            data.extend(request_mac);
+        }
+        data.extend(message);
+        self.name.to_lowercase().encode(&mut data);
+        data.extend(ANY.to_be_bytes());
+        data.extend(0u32.to_be_bytes());
+        algorithm.to_lowercase().encode(&mut data);
+        data.extend(&time_signed.to_be_bytes()[2..]);
+        data.extend(fudge.to_be_bytes());
+        data.extend(error.to_be_bytes());
+        data.extend(u16::try_from(other.len())?.to_be_bytes());
+        data.extend(other);
+        Ok(data)
+    }
+
+    fn hmac_key(&self) -> hmac::Key {
+        hmac::Key::new(hmac::HMAC_SHA256, &self.secret)
+    }
+}
+
+// Find the last record of a message, returning its offset along with it.
+fn last_record(message: &[u8]) -> Result<(usize, Record), Error> {
+    let mut r = Reader::new(message);
+    let header = r.bytes(12)?;
+    let count = |i: usize| u16::from_be_bytes([header[i], header[i + 1]]) as usize;
+    let records = count(6) + count(8) + count(10);
+    if count(10) == 0 {
+        bail!("the message is not signed");
+    }
+    for _ in 0..count(4) {
+        r.question()?;
+    }
+    for _ in 1..records {
+        r.record()?;
+    }
+    let start = r.pos();
+    let record = r.record()?;
+    if r.pos() != message.len() {
+        bail!("unexpected data after the last record");
+    }
+    Ok((start, record))
+}
+
+// The current time in seconds since the Unix epoch.
+fn now() -> Result<u64, Error> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::Message;
+    use crate::query::Query;
+
+    fn key() -> Key {
+        "hmac-sha256:update-key:c2VjcmV0IHNoYXJlZCB3aXRoIHRoZSBzZXJ2ZXI="
+            .parse()
+            .unwrap()
+    }
+
+    fn query() -> Vec<u8> {
+        let mut buf = Vec::new();
+        Query::new("example.com", RecordType::A)
+            .encode(&mut buf)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn parse_keys() {
+        let key = key();
+        assert_eq!(key.name.to_string(), "update-key.");
+        assert_eq!(key.secret, b"secret shared with the server");
+        assert!("update-key:c2VjcmV0".parse::<Key>().is_ok());
+        assert!("hmac-md5:update-key:c2VjcmV0".parse::<Key>().is_err());
+        assert!("update-key:not base64!".parse::<Key>().is_err());
+        assert!("c2VjcmV0".parse::<Key>().is_err());
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let mut request = query();
+        let request_mac = key().sign(&mut request, None).unwrap();
+        let parsed = Message::try_from(request.as_slice()).unwrap();
+        assert_eq!(parsed.additional.last().unwrap().r#type, RecordType::TSIG);
+        assert_eq!(key().verify(&request, None).unwrap(), request_mac);
+
+        // A response is signed along with the request's MAC.
+        let mut response = query();
+        response[2] |= 0x80;
+        key().sign(&mut response, Some(&request_mac)).unwrap();
+        assert!(key().verify(&response, Some(&request_mac)).is_ok());
+        assert!(key().verify(&response, None).is_err());
+        assert!(key().verify(&query(), None).is_err());
+
+        // Any change to the message or a different secret breaks the signature.
+        let mut tampered = request.clone();
+        tampered[3] ^= 0x01;
+        assert!(key().verify(&tampered, None).is_err());
+        let other = Key::new(key().name, b"another secret".to_vec());
+        assert!(other.verify(&request, None).is_err());
+
+        // There is no room in ARCOUNT for the TSIG record.
+        let mut full = query();
+        full[10..12].copy_from_slice(&u16::MAX.to_be_bytes());
+        let unsigned = full.clone();
+        assert!(key().sign(&mut full, None).is_err());
+        assert_eq!(full, unsigned);
+    }
+}
@@ -0,0 +1,487 @@
+use crate::message::{Flags, Header, Message, Question, Record};
+use crate::name::Name;
+use crate::rdata::{RData, RecordType};
+use crate::transport::Options;
+use crate::tsig::Key;
+use crate::zone::{parse_name, parse_record};
+use anyhow::{anyhow, bail, Context, Error};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::time::Instant;
+
+// The UPDATE opcode (RFC 2136 section 1.3).
+const UPDATE: u8 = 5;
+
+// Classes with a special meaning in the prerequisite and update sections.
+const IN: u16 = 1;
+const NONE: u16 = 254;
+const ANY: u16 = 255;
+
+// Largest UPDATE sent over UDP. Bigger ones go over TCP, as EDNS isn't used.
+const MAX_UDP_SIZE: usize = 512;
+
+// A condition that must hold for the server to apply an update (RFC 2136 section 2.4). If any
+// of them doesn't, nothing is changed.
+#[derive(Debug, Clone)]
+pub enum Prerequisite {
+    // The name has an RRset of the type.
+    Exists(Name, RecordType),
+    // The name has the record. When given for every record of an RRset, the RRset must be
+    // exactly those records.
+    Matches(Record),
+    // The name has no RRset of the type.
+    NotExists(Name, RecordType),
+    // The name has at least one record.
+    NameInUse(Name),
+    // The name has no records at all.
+    NameNotInUse(Name),
+}
+
+// A change to the zone (RFC 2136 section 2.5).
+#[derive(Debug, Clone)]
+pub enum Change {
+    // Add the record. Adding a record that already exists does nothing.
+    Add(Record),
+    // Delete the RRset of the type at the name.
+    DeleteRRset(Name, RecordType),
+    // Delete every record at the name.
+    DeleteName(Name),
+    // Delete the record.
+    Delete(Record),
+}
+
+// What a command line argument refers to: every record at a name, one RRset, or one record.
+enum Target {
+    Name(Name),
+    RRset(Name, RecordType),
+    Record(Record),
+}
+
+// Parse "name", "name type" or a whole record in master file format. Names are relative to the
+// zone, and the TTL and class are optional.
+fn target(s: &str, zone: &Name) -> Result<Target, Error> {
+    let mut tokens = s.split_whitespace();
+    let name = parse_name(tokens.next().ok_or(anyhow!("missing name"))?, zone)?;
+    let mut rest = tokens.skip_while(|t| {
+        t.starts_with(|c: char| c.is_ascii_digit()) || t.eq_ignore_ascii_case("IN")
+    });
+    Ok(match (rest.next(), rest.next()) {
+        (None, _) => Target::Name(name),
+        (Some(t), None) => Target::RRset(
+            name,
+            t.to_ascii_uppercase()
+                .parse()
+                .map_err(|_| anyhow!("unknown record type {t}"))?,
+        ),
+        _ => Target::Record(parse_record(s, zone, Some(0))?),
+    })
+}
+
+impl Prerequisite {
+    // Parse a prerequisite that something exists: a name with any records, an RRset given as
+    // "name type", or a record.
+    pub fn parse(s: &str, zone: &Name) -> Result<Self, Error> {
+        Ok(match target(s, zone)? {
+            Target::Name(name) => Prerequisite::NameInUse(name),
+            Target::RRset(name, r#type) => Prerequisite::Exists(name, r#type),
+            Target::Record(record) => Prerequisite::Matches(record),
+        })
+    }
+
+    // Parse a prerequisite that something doesn't exist: a name, or an RRset given as
+    // "name type".
+    pub fn parse_absent(s: &str, zone: &Name) -> Result<Self, Error> {
+        match target(s, zone)? {
+            Target::Name(name) => Ok(Prerequisite::NameNotInUse(name)),
+            Target::RRset(name, r#type) => Ok(Prerequisite::NotExists(name, r#type)),
+            Target::Record(_) => bail!("only names and RRsets can be required to be absent"),
+        }
+    }
+
+    // The prerequisite as it goes in the prerequisite section.
+    fn record(&self) -> Record {
+        match self {
+            Prerequisite::Exists(name, r#type) => empty(name, *r#type, ANY),
+            Prerequisite::Matches(record) => Record {
+                ttl: 0,
+                ..record.clone()
+            },
+            Prerequisite::NotExists(name, r#type) => empty(name, *r#type, NONE),
+            Prerequisite::NameInUse(name) => empty(name, RecordType::ANY, ANY),
+            Prerequisite::NameNotInUse(name) => empty(name, RecordType::ANY, NONE),
+        }
+    }
+}
+
+impl Change {
+    // Parse a record to add. It must have a TTL.
+    pub fn parse_add(s: &str, zone: &Name) -> Result<Self, Error> {
+        Ok(Change::Add(parse_record(s, zone, None)?))
+    }
+
+    // Parse what to delete: every record at a name, an RRset given as "name type", or a record.
+    pub fn parse_delete(s: &str, zone: &Name) -> Result<Self, Error> {
+        Ok(match target(s, zone)? {
+            Target::Name(name) => Change::DeleteName(name),
+            Target::RRset(name, r#type) => Change::DeleteRRset(name, r#type),
+            Target::Record(record) => Change::Delete(record),
+        })
+    }
+
+    // The change as it goes in the update section.
+    fn record(&self) -> Record {
+        match self {
+            Change::Add(record) => record.clone(),
+            Change::DeleteRRset(name, r#type) => empty(name, *r#type, ANY),
+            Change::DeleteName(name) => empty(name, RecordType::ANY, ANY),
+            Change::Delete(record) => Record {
+                class: NONE,
+                ttl: 0,
+                ..record.clone()
+            },
+        }
+    }
+}
+
+// A record without RDATA, which refers to a whole RRset (or every RRset with type ANY). The class
+// says what to do with it.
+fn empty(name: &Name, r#type: RecordType, class: u16) -> Record {
+    Record {
+        name: name.clone(),
+        r#type,
+        class,
+        ttl: 0,
+        rdata: RData::Unknown(Vec::new()),
+    }
+}
+
+// A dynamic update to a zone (RFC 2136). The server checks every prerequisite, then applies all
+// of the changes, or none of them.
+pub struct Update {
+    pub id: u16,
+    pub zone: Name,
+    pub prerequisites: Vec<Prerequisite>,
+    pub changes: Vec<Change>,
+    // Sign the update with TSIG. Servers usually only accept signed updates.
+    pub key: Option<Key>,
+}
+
+impl Update {
+    // Create an empty update with a random transaction ID.
+    pub fn new(zone: Name) -> Self {
+        Update {
+            id: rand::random(),
+            zone,
+            prerequisites: Vec::new(),
+            changes: Vec::new(),
+            key: None,
+        }
+    }
+
+    // Build the UPDATE message. It has the same sections as a query, under different names:
+    //
+    // +---------------------+
+    // |        Header       |
+    // +---------------------+
+    // |         Zone        | the zone's name and the SOA type
+    // +---------------------+
+    // |     Prerequisite    | RRs or RRsets which must (not) exist
+    // +---------------------+
+    // |        Update       | RRs or RRsets to be added or deleted
+    // +---------------------+
+    // |   Additional Data   | the TSIG record, if the update is signed
+    // +---------------------+
+    pub fn message(&self) -> Message {
+        let answers: Vec<_> = self.prerequisites.iter().map(|p| p.record()).collect();
+        let authority: Vec<_> = self.changes.iter().map(|c| c.record()).collect();
+        Message {
+            header: Header {
+                id: self.id,
+                flags: Flags {
+                    opcode: UPDATE,
+                    ..Flags::default()
+                },
+                qdcount: 1,
+                ancount: answers.len() as u16,
+                nscount: authority.len() as u16,
+                arcount: 0,
+            },
+            questions: vec![Question {
+                name: self.zone.clone(),
+                r#type: RecordType::SOA,
+                class: IN,
+            }],
+            answers,
+            authority,
+            additional: Vec::new(),
+        }
+    }
+
+    // Send the update to a server, which should be the zone's primary, and check the response.
+    // The update is sent over UDP unless it's too big or TCP is forced. It is only sent once,
+    // since repeating an update that was applied could make its prerequisites fail.
+    pub fn send(&self, server: SocketAddr, options: &Options) -> Result<Message, Error> {
+        if options.tls.is_some() || options.doh.is_some() {
+            bail!("updates can only be sent over UDP or TCP");
+        }
+        let mut request = self.message().encode()?;
+        let request_mac = match &self.key {
+            Some(key) => Some(key.sign(&mut request, None)?),
+            None => None,
+        };
+
+        let mut response = None;
+        if !options.tcp && request.len() <= MAX_UDP_SIZE {
+            response = Some(self.udp(&request, request_mac.as_deref(), server, options)?);
+        }
+        let buf = match response {
+            Some(buf) if buf[2] & 0x02 == 0 => buf,
+            _ => self.tcp(&request, server, options)?,
+        };
+        let response = Message::try_from(buf.as_slice())?;
+        let failed = || format!("update of {} failed", self.zone);
+        if let Some(key) = &self.key {
+            // Servers that can't check our signature answer with an unsigned error RCODE, which
+            // says more than the missing signature.
+            if let Err(err) = key.verify(&buf, request_mac.as_deref()) {
+                response.check_rcode().with_context(failed)?;
+                return Err(err.context("could not verify the response"));
+            }
+        }
+        response.check_rcode().with_context(failed)?;
+        Ok(response)
+    }
+
+    // Whether a message answers this update.
+    fn answers(&self, buf: &[u8]) -> bool {
+        match Message::try_from(buf) {
+            Ok(response) => {
+                let flags = response.header.flags;
+                response.header.id == self.id && flags.qr && flags.opcode == UPDATE
+            }
+            Err(_) => false,
+        }
+    }
+
+    // Send the update in a datagram and wait for the response, ignoring any that don't match. A
+    // signed update waits for a response with a valid signature, so a spoofed datagram can't make
+    // it fail. If none arrives in time, the first one that matched is returned so its RCODE can
+    // be reported.
+    fn udp(
+        &self,
+        request: &[u8],
+        request_mac: Option<&[u8]>,
+        server: SocketAddr,
+        options: &Options,
+    ) -> Result<Vec<u8>, Error> {
+        let local: SocketAddr = match server {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(server)?;
+        socket.send(request)?;
+        let deadline = Instant::now() + options.timeout;
+        let mut buf = vec![0; u16::MAX as usize];
+        let mut unverified = None;
+        loop {
+            let Some(remaining) = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|d| !d.is_zero())
+            else {
+                return unverified.ok_or(anyhow!("timed out after {:?}", options.timeout));
+            };
+            socket.set_read_timeout(Some(remaining))?;
+            let n = match socket.recv(&mut buf) {
+                Ok(n) => n,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if !self.answers(&buf[..n]) {
+                continue;
+            }
+            match &self.key {
+                Some(key) if key.verify(&buf[..n], request_mac).is_err() => {
+                    unverified.get_or_insert_with(|| buf[..n].to_vec());
+                }
+                _ => return Ok(buf[..n].to_vec()),
+            }
+        }
+    }
+
+    // Send the update over a TCP connection, with the length prefix of RFC 1035 section 4.2.2.
+    fn tcp(&self, request: &[u8], server: SocketAddr, options: &Options) -> Result<Vec<u8>, Error> {
+        let mut stream = TcpStream::connect_timeout(&server, options.timeout)?;
+        stream.set_read_timeout(Some(options.timeout))?;
+        stream.set_write_timeout(Some(options.timeout))?;
+        let mut framed = u16::try_from(request.len())?.to_be_bytes().to_vec();
+        framed.extend(request);
+        stream.write_all(&framed)?;
+        let mut length = [0; 2];
+        stream.read_exact(&mut length)?;
+        let mut buf = vec![0; u16::from_be_bytes(length) as usize];
+        stream.read_exact(&mut buf)?;
+        if !self.answers(&buf) {
+            bail!("the response does not match the update");
+        }
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    fn zone() -> Name {
+        "example.com".parse().unwrap()
+    }
+
+    fn key() -> Key {
+        "update-key:c2VjcmV0IHNoYXJlZCB3aXRoIHRoZSBzZXJ2ZXI="
+            .parse()
+            .unwrap()
+    }
+
+    fn update() -> Update {
+        let zone = zone();
+        let mut update = Update::new(zone.clone());
+        update.prerequisites = vec![
+            Prerequisite::parse("www", &zone).unwrap(),
+            Prerequisite::parse("www A", &zone).unwrap(),
+            Prerequisite::parse("www IN A 192.0.2.1", &zone).unwrap(),
+            Prerequisite::parse_absent("new", &zone).unwrap(),
+            Prerequisite::parse_absent("www AAAA", &zone).unwrap(),
+        ];
+        update.changes = vec![
+            Change::parse_add("new 300 A 192.0.2.2", &zone).unwrap(),
+            Change::parse_delete("old", &zone).unwrap(),
+            Change::parse_delete("www.example.com. TXT", &zone).unwrap(),
+            Change::parse_delete("www 3600 A 192.0.2.1", &zone).unwrap(),
+        ];
+        update
+    }
+
+    // Run a stand-in primary that checks the signature on one update and answers it with the
+    // given RCODE in a signed response. A tampered response has its AA bit flipped after
+    // signing. A spoofed response, the same but unsigned and with RCODE REFUSED, is sent ahead
+    // of the real one.
+    fn primary(rcode: u8, tampered: bool, spoofed: bool) -> SocketAddr {
+        let key = key();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (n, from) = socket.recv_from(&mut buf).unwrap();
+            let request_mac = key.verify(&buf[..n], None).unwrap();
+            let request = Message::try_from(&buf[..n]).unwrap();
+            let response = |rcode| {
+                Message {
+                    header: Header {
+                        flags: Flags {
+                            qr: true,
+                            opcode: UPDATE,
+                            rcode,
+                            ..Flags::default()
+                        },
+                        ..request.header
+                    },
+                    questions: request.questions.clone(),
+                    answers: Vec::new(),
+                    authority: Vec::new(),
+                    additional: Vec::new(),
+                }
+                .encode()
+                .unwrap()
+            };
+            if spoofed {
+                // REFUSED
+                socket.send_to(&response(5), from).unwrap();
+            }
+            let mut response = response(rcode);
+            key.sign(&mut response, Some(&request_mac)).unwrap();
+            if tampered {
+                response[2] ^= 0x04;
+            }
+            socket.send_to(&response, from).unwrap();
+        });
+        addr
+    }
+
+    fn options() -> Options {
+        Options {
+            timeout: Duration::from_secs(1),
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn build_update() {
+        let update = update();
+        let encoded = update.message().encode().unwrap();
+        let message = Message::try_from(encoded.as_slice()).unwrap();
+        assert_eq!(message.header.flags.opcode, UPDATE);
+        assert_eq!(message.questions[0].r#type, RecordType::SOA);
+        let summary = |records: &[Record]| -> Vec<String> {
+            records
+                .iter()
+                .map(|r| format!("{} {} {} {}", r.name, r.class, r.r#type, r.rdata))
+                .collect()
+        };
+        assert_eq!(
+            summary(&message.answers),
+            [
+                "www.example.com. 255 ANY \\# 0",
+                "www.example.com. 255 A \\# 0",
+                "www.example.com. 1 A 192.0.2.1",
+                "new.example.com. 254 ANY \\# 0",
+                "www.example.com. 254 AAAA \\# 0",
+            ]
+        );
+        assert_eq!(
+            summary(&message.authority),
+            [
+                "new.example.com. 1 A 192.0.2.2",
+                "old.example.com. 255 ANY \\# 0",
+                "www.example.com. 255 TXT \\# 0",
+                "www.example.com. 254 A 192.0.2.1",
+            ]
+        );
+        assert!(message.answers.iter().all(|r| r.ttl == 0));
+        assert_eq!(message.authority[0].ttl, 300);
+        assert!(Prerequisite::parse_absent("www A 192.0.2.1", &zone()).is_err());
+        assert!(Change::parse_add("www A 192.0.2.1", &zone()).is_err());
+    }
+
+    #[test]
+    fn send_signed_update() {
+        let mut update = update();
+        update.key = Some(key());
+        let response = update.send(primary(0, false, false), &options()).unwrap();
+        assert_eq!(response.header.id, update.id);
+
+        // Prerequisites that don't hold come back as an error RCODE.
+        let err = update
+            .send(primary(8, false, false), &options())
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("NXRRSET"), "{err:#}");
+
+        // A response changed on the way is rejected.
+        let err = update
+            .send(primary(0, true, false), &options())
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("does not match"), "{err:#}");
+
+        // An unsigned response ahead of the signed one is passed over.
+        let response = update.send(primary(0, false, true), &options()).unwrap();
+        assert_eq!(response.header.flags.rcode, 0);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::message::{Flags, Header, Message, Question, Record};
+use crate::name::Name;
+use crate::rdata::{RData, RecordType};
+use anyhow::{anyhow, bail, Error};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+// The kind of zone transfer to ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    // The whole zone (RFC 5936).
+    Axfr,
+    // The changes since the given serial (RFC 1995). Servers may send the whole zone instead.
+    Ixfr(u32),
+}
+
+// Where a transferred record belongs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    // A record of the zone as it is now, from an AXFR or an IXFR that sent the whole zone. The
+    // first and last records are the zone's SOA.
+    Zone,
+    // A record removed by one of the changes of an incremental transfer. Each set of deletions
+    // starts with the SOA of the version it applies to.
+    Deleted,
+    // A record added by one of the changes of an incremental transfer. Each set of additions
+    // starts with the SOA of the version it leads to.
+    Added,
+}
+
+// Transfer a zone from a server over TCP, passing each record to on_record as soon as it arrives
+// so large zones don't have to be held in memory. Returns the number of records received.
+//
+// The response is a stream of messages, which ends with a repeat of the first record: the
+// zone's current SOA. An incremental transfer is a sequence of changes, each one an old SOA and
+// the records it deleted followed by a new SOA and the records it added:
+//
+//     SOA 3 | SOA 1, deleted... | SOA 2, added... | SOA 2, deleted... | SOA 3, added... | SOA 3
+//
+// A response with nothing but the current SOA means the zone hasn't changed since the serial, as
+// long as the SOA's serial is no newer than ours (RFC 1995 section 2). A newer one is just the
+// start of a transfer that the server may send in a message of its own, and a server that hangs
+// up after it has newer data without having sent it.
+pub fn transfer(
+    zone: &str,
+    kind: Transfer,
+    server: SocketAddr,
+    timeout: Duration,
+    mut on_record: impl FnMut(Section, &Record),
+) -> Result<usize, Error> {
+    let zone: Name = zone.parse()?;
+    let request = request(&zone, kind, rand::random());
+    let mut stream = TcpStream::connect_timeout(&server, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let encoded = request.encode()?;
+    let mut framed = u16::try_from(encoded.len())?.to_be_bytes().to_vec();
+    framed.extend(encoded);
+    stream.write_all(&framed)?;
+
+    let mut state = State::default();
+    let mut count = 0;
+    loop {
+        let Some(response) = read_message(&mut stream)? else {
+            if let (Transfer::Ixfr(ours), Some(current), 1) = (kind, state.serial, count) {
+                bail!("server has serial {current}, newer than {ours}, but sent no changes");
+            }
+            bail!("transfer ended early after {count} records");
+        };
+        check_response(&request, &response, count == 0)?;
+        match (response.answers.is_empty(), count) {
+            (true, 0) => bail!("server sent no records, it may not allow transfers"),
+            (true, _) => bail!("transfer ended early after {count} records"),
+            _ => {}
+        }
+        for record in &response.answers {
+            count += 1;
+            if state.done {
+                bail!(
+                    "unexpected {} record after the end of the transfer",
+                    record.r#type
+                );
+            }
+            on_record(state.next(kind, record)?, record);
+        }
+        if state.done {
+            return Ok(count);
+        }
+        if let (Transfer::Ixfr(ours), Some(current), 1) = (kind, state.serial, count) {
+            // Serials are compared with serial number arithmetic (RFC 1982).
+            if current.wrapping_sub(ours) as i32 <= 0 {
+                return Ok(count);
+            }
+        }
+    }
+}
+
+// The request for a transfer. An IXFR carries the SOA of the version we have in its authority
+// section; only the serial in it matters.
+fn request(zone: &Name, kind: Transfer, id: u16) -> Message {
+    let r#type = match kind {
+        Transfer::Axfr => RecordType::AXFR,
+        Transfer::Ixfr(_) => RecordType::IXFR,
+    };
+    let authority = match kind {
+        Transfer::Axfr => Vec::new(),
+        Transfer::Ixfr(serial) => vec![Record {
+            name: zone.clone(),
+            r#type: RecordType::SOA,
+            class: 1,
+            ttl: 0,
+            rdata: RData::SOA {
+                mname: Name::root(),
+                rname: Name::root(),
+                serial,
+                refresh: 0,
+                retry: 0,
+                expire: 0,
+                minimum: 0,
+            },
+        }],
+    };
+    Message {
+        header: Header {
+            id,
+            flags: Flags::default(),
+            qdcount: 1,
+            ancount: 0,
+            nscount: authority.len() as u16,
+            arcount: 0,
+        },
+        questions: vec![Question {
+            name: zone.clone(),
+            r#type,
+            class: 1,
+        }],
+        answers: Vec::new(),
+        authority,
+        additional: Vec::new(),
+    }
+}
+
+// Read the next length-prefixed message, or None if the other side closed the connection.
+fn read_message(stream: &mut TcpStream) -> Result<Option<Message>, Error> {
+    let mut length = [0; 2];
+    match stream.read_exact(&mut length) {
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    let mut buf = vec![0; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut buf)?;
+    Message::try_from(buf.as_slice()).map(Some)
+}
+
+// Every message must answer our request. Only the first one has to repeat the question (RFC 5936
+// section 2.2.1).
+fn check_response(request: &Message, response: &Message, first: bool) -> Result<(), Error> {
+    if response.header.id != request.header.id {
+        bail!(
+            "ID {} does not match query ID {}",
+            response.header.id,
+            request.header.id
+        );
+    }
+    if !response.header.flags.qr {
+        bail!("QR bit is not set");
+    }
+    response.check_rcode()?;
+    let question = &request.questions[0];
+    match response.questions.as_slice() {
+        [] if !first => Ok(()),
+        [q] if q.name == question.name && q.r#type == question.r#type => Ok(()),
+        [q] => bail!("question {} {} does not match the query", q.name, q.r#type),
+        questions => bail!("expected 1 question, got {}", questions.len()),
+    }
+}
+
+// Tracks where we are in the stream of records.
+#[derive(Default)]
+struct State {
+    // The serial of the first SOA, which is the zone's current version.
+    serial: Option<u32>,
+    // Whether the second record was an older SOA, which makes this an incremental transfer.
+    incremental: bool,
+    section: Option<Section>,
+    records: usize,
+    done: bool,
+}
+
+impl State {
+    // Work out which section a record is in. The SOAs at the start and end of the transfer are
+    // in the Zone section.
+    fn next(&mut self, kind: Transfer, record: &Record) -> Result<Section, Error> {
+        self.records += 1;
+        let soa = match record.rdata {
+            RData::SOA { serial, .. } => Some(serial),
+            _ => None,
+        };
+        if self.records == 1 {
+            self.serial = Some(soa.ok_or(anyhow!("transfer does not start with an SOA record"))?);
+            return Ok(Section::Zone);
+        }
+        if self.records == 2 {
+            // An SOA with the current serial here is the end of a zone with nothing else in it.
+            self.incremental =
+                soa.is_some() && soa != self.serial && matches!(kind, Transfer::Ixfr(_));
+            if !self.incremental {
+                self.section = Some(Section::Zone);
+            }
+        }
+        // The current SOA again ends the transfer. In an incremental transfer it also starts the
+        // last set of additions, so it only counts once we're past the deletions.
+        if soa == self.serial && (!self.incremental || self.section == Some(Section::Added)) {
+            self.done = true;
+            return Ok(Section::Zone);
+        }
+        if self.incremental && soa.is_some() {
+            self.section = match self.section {
+                None | Some(Section::Added) => Some(Section::Deleted),
+                _ => Some(Section::Added),
+            };
+        }
+        Ok(self.section.unwrap_or(Section::Zone))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn soa(serial: u32) -> Record {
+        record(RData::SOA {
+            mname: "ns.example".parse().unwrap(),
+            rname: "hostmaster.example".parse().unwrap(),
+            serial,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: 300,
+        })
+    }
+
+    fn a(last: u8) -> Record {
+        record(RData::A([192, 0, 2, last].into()))
+    }
+
+    fn record(rdata: RData) -> Record {
+        let r#type = match rdata {
+            RData::SOA { .. } => RecordType::SOA,
+            _ => RecordType::A,
+        };
+        Record {
+            name: "example".parse().unwrap(),
+            r#type,
+            class: 1,
+            ttl: 300,
+            rdata,
+        }
+    }
+
+    // Run a stand-in server that answers one transfer request with the given messages. The
+    // request is sent back on the channel.
+    fn serve(messages: Vec<Vec<Record>>) -> (SocketAddr, mpsc::Receiver<Message>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap();
+        let (requests, received) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = read_message(&mut stream).unwrap().unwrap();
+            for (i, answers) in messages.into_iter().enumerate() {
+                let mut response = reply(&request, answers);
+                // Only the first message repeats the question.
+                if i > 0 {
+                    response.questions.clear();
+                }
+                let encoded = response.encode().unwrap();
+                stream
+                    .write_all(&(encoded.len() as u16).to_be_bytes())
+                    .unwrap();
+                stream.write_all(&encoded).unwrap();
+            }
+            requests.send(request).unwrap();
+        });
+        (server, received)
+    }
+
+    fn reply(request: &Message, answers: Vec<Record>) -> Message {
+        Message {
+            header: Header {
+                flags: Flags {
+                    qr: true,
+                    aa: true,
+                    ..Flags::default()
+                },
+                ..request.header
+            },
+            questions: request.questions.clone(),
+            answers,
+            authority: Vec::new(),
+            additional: Vec::new(),
+        }
+    }
+
+    fn run(kind: Transfer, messages: Vec<Vec<Record>>) -> (Vec<String>, Message) {
+        let (server, requests) = serve(messages);
+        let mut records = Vec::new();
+        let count = transfer("example", kind, server, Duration::from_secs(1), |s, r| {
+            records.push(format!("{s:?} {} {}", r.r#type, r.rdata))
+        })
+        .unwrap();
+        assert_eq!(count, records.len());
+        (records, requests.recv().unwrap())
+    }
+
+    #[test]
+    fn transfer_whole_zone() {
+        let (records, request) = run(Transfer::Axfr, vec![vec![soa(3), a(1)], vec![a(2), soa(3)]]);
+        assert_eq!(request.questions[0].r#type, RecordType::AXFR);
+        assert_eq!(
+            records,
+            [
+                "Zone SOA ns.example. hostmaster.example. 3 3600 600 86400 300",
+                "Zone A 192.0.2.1",
+                "Zone A 192.0.2.2",
+                "Zone SOA ns.example. hostmaster.example. 3 3600 600 86400 300",
+            ]
+        );
+    }
+
+    #[test]
+    fn transfer_changes() {
+        // Version 1 had 192.0.2.1, version 2 replaced it with 192.0.2.2, and version 3 added
+        // 192.0.2.3.
+        let (records, request) = run(
+            Transfer::Ixfr(1),
+            vec![
+                vec![soa(3), soa(1), a(1), soa(2), a(2)],
+                vec![soa(2), soa(3), a(3), soa(3)],
+            ],
+        );
+        assert_eq!(request.questions[0].r#type, RecordType::IXFR);
+        assert!(matches!(
+            request.authority[0].rdata,
+            RData::SOA { serial: 1, .. }
+        ));
+        let sections: Vec<_> = records
+            .iter()
+            .map(|r| r.split_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(
+            sections,
+            ["Zone", "Deleted", "Deleted", "Added", "Added", "Deleted", "Added", "Added", "Zone"]
+        );
+    }
+
+    #[test]
+    fn transfer_changes_after_lone_soa() {
+        // The first message holds only the current SOA, which isn't the end of the transfer.
+        let (records, _) = run(
+            Transfer::Ixfr(2),
+            vec![vec![soa(3)], vec![soa(2), a(2), soa(3), a(3), soa(3)]],
+        );
+        let sections: Vec<_> = records
+            .iter()
+            .map(|r| r.split_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(
+            sections,
+            ["Zone", "Deleted", "Deleted", "Added", "Added", "Zone"]
+        );
+    }
+
+    #[test]
+    fn transfer_up_to_date() {
+        let (records, _) = run(Transfer::Ixfr(3), vec![vec![soa(3)]]);
+        assert_eq!(records.len(), 1);
+        let (records, _) = run(Transfer::Ixfr(4), vec![vec![soa(3)]]);
+        assert_eq!(records.len(), 1);
+        // A newer SOA followed by the end of the stream means changes are missing.
+        let (server, _) = serve(vec![vec![soa(3)]]);
+        let err = transfer(
+            "example",
+            Transfer::Ixfr(2),
+            server,
+            Duration::from_secs(1),
+            |_, _| {},
+        );
+        assert!(err.unwrap_err().to_string().contains("newer than 2"));
+    }
+
+    #[test]
+    fn reject_broken_transfers() {
+        let (server, _) = serve(vec![vec![a(1), soa(3)]]);
+        let err = transfer(
+            "example",
+            Transfer::Axfr,
+            server,
+            Duration::from_secs(1),
+            |_, _| {},
+        );
+        assert!(err.is_err());
+        // The server hangs up before the closing SOA.
+        let (server, _) = serve(vec![vec![soa(3), a(1)]]);
+        let err = transfer(
+            "example",
+            Transfer::Axfr,
+            server,
+            Duration::from_secs(1),
+            |_, _| {},
+        );
+        assert!(err.unwrap_err().to_string().contains("ended early"));
+    }
+}
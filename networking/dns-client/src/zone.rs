@@ -0,0 +1,532 @@
+use crate::message::Record;
+use crate::name::Name;
+use crate::rdata::{RData, RecordType};
+use anyhow::{anyhow, bail, Context, Error};
+use std::collections::HashMap;
+
+// The records of a zone loaded from a master file, grouped by owner name. Every record is at or
+// below the owner of the zone's SOA record, which is the origin.
+pub struct Zone {
+    pub origin: Name,
+    records: HashMap<Name, Vec<Record>>,
+}
+
+impl Zone {
+    // Parse a zone in master file format (RFC 1035 section 5.1), like a BIND zone file:
+    //
+    //     $ORIGIN example.com.
+    //     $TTL 1h
+    //     @       IN  SOA  ns1 hostmaster (
+    //                      2024010101 ; serial
+    //                      1d 2h 4w 1h )
+    //             IN  NS   ns1
+    //             IN  MX   10 mail
+    //     ns1         A    192.0.2.1
+    //     www     300 A    192.0.2.2
+    //                 TXT  "v=spf1 -all"
+    //
+    // Names without a trailing dot are relative to the origin, which starts out as the given one
+    // and is changed by $ORIGIN directives. A record without an owner (a line starting with
+    // whitespace) belongs to the previous owner. Records without a TTL get the one from the last
+    // $TTL directive, or else the TTL of the previous record. Only the IN class and the A, AAAA,
+    // CNAME, MX, NS, SOA and TXT types are supported. $INCLUDE isn't.
+    pub fn parse(text: &str, origin: Name) -> Result<Self, Error> {
+        let mut parser = Parser {
+            origin,
+            default_ttl: None,
+            last_owner: None,
+            last_ttl: None,
+        };
+        let mut records = Vec::new();
+        for entry in entries(text)? {
+            let line = entry.line;
+            if let Some(record) = parser
+                .entry(entry)
+                .with_context(|| format!("line {line}"))?
+            {
+                records.push(record);
+            }
+        }
+
+        let mut soa = records.iter().filter(|r| r.r#type == RecordType::SOA);
+        let origin = match (soa.next(), soa.next()) {
+            (Some(soa), None) => soa.name.clone(),
+            (None, _) => bail!("the zone has no SOA record"),
+            (Some(_), Some(_)) => bail!("the zone has more than one SOA record"),
+        };
+        let mut zone = Zone {
+            origin,
+            records: HashMap::new(),
+        };
+        for record in records {
+            if !record.name.is_subdomain_of(&zone.origin) {
+                bail!("{} is outside of the zone {}", record.name, zone.origin);
+            }
+            zone.records
+                .entry(record.name.clone())
+                .or_default()
+                .push(record);
+        }
+        Ok(zone)
+    }
+
+    // The SOA record at the origin.
+    pub fn soa(&self) -> &Record {
+        self.records[&self.origin]
+            .iter()
+            .find(|r| r.r#type == RecordType::SOA)
+            .expect("zone has an SOA record")
+    }
+
+    // The records owned by a name.
+    pub fn records(&self, name: &Name) -> &[Record] {
+        self.records.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    // Whether a name exists in the zone: it owns records, or has descendants that do (an empty
+    // non-terminal, RFC 8020).
+    pub fn contains(&self, name: &Name) -> bool {
+        self.records.keys().any(|owner| owner.is_subdomain_of(name))
+    }
+
+    // The NS records of the delegation a name is at or below, if any. The origin's own NS records
+    // aren't a delegation.
+    pub fn delegation(&self, name: &Name) -> Option<&[Record]> {
+        let depth = self.origin.labels().len();
+        let labels = name.labels();
+        (depth + 1..=labels.len()).find_map(|n| {
+            let ancestor = Name::from_labels(labels[labels.len() - n..].to_vec()).ok()?;
+            let records = self.records(&ancestor);
+            records
+                .iter()
+                .any(|r| r.r#type == RecordType::NS)
+                .then_some(records)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+// Parse a single record in master file format, like "www 300 IN A 192.0.2.1", with names relative
+// to the origin. A record without a TTL gets the default one, if there is one.
+pub fn parse_record(text: &str, origin: &Name, default_ttl: Option<u32>) -> Result<Record, Error> {
+    let mut parser = Parser {
+        origin: origin.clone(),
+        default_ttl,
+        last_owner: None,
+        last_ttl: None,
+    };
+    let mut entries = entries(text)?.into_iter();
+    match (entries.next(), entries.next()) {
+        (Some(entry), None) => parser
+            .entry(entry)?
+            .ok_or(anyhow!("expected a record, not a directive")),
+        _ => bail!("expected a single record"),
+    }
+}
+
+// A name from a master file: absolute with a trailing dot, @ for the origin, or else relative to
+// the origin.
+pub fn parse_name(s: &str, origin: &Name) -> Result<Name, Error> {
+    if s == "@" {
+        return Ok(origin.clone());
+    }
+    let name: Name = s.parse()?;
+    if s.ends_with('.') && !s.ends_with("\\.") {
+        return Ok(name);
+    }
+    let mut labels = name.labels().to_vec();
+    labels.extend(origin.labels().iter().cloned());
+    Name::from_labels(labels)
+        .with_context(|| format!("{s} is too long once the origin {origin} is added"))
+}
+
+// A logical line of the master file: parentheses let an entry span several lines.
+struct Entry {
+    line: usize,
+    // Whether the entry starts with whitespace, so it has no owner of its own.
+    indented: bool,
+    tokens: Vec<Token>,
+}
+
+struct Token {
+    // The text of the token, with backslash escapes left in place.
+    text: String,
+    quoted: bool,
+}
+
+// Split the file into entries, dropping comments and blank lines.
+fn entries(text: &str) -> Result<Vec<Entry>, Error> {
+    let mut entries = Vec::new();
+    let mut line = 1;
+    let mut entry = Entry {
+        line,
+        indented: false,
+        tokens: Vec::new(),
+    };
+    let mut start_of_line = true;
+    let mut depth = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if start_of_line && depth == 0 {
+            entry.line = line;
+            entry.indented = c == ' ' || c == '\t';
+        }
+        start_of_line = false;
+        match c {
+            '\n' => {
+                line += 1;
+                start_of_line = true;
+                if depth == 0 && !entry.tokens.is_empty() {
+                    entries.push(std::mem::replace(
+                        &mut entry,
+                        Entry {
+                            line,
+                            indented: false,
+                            tokens: Vec::new(),
+                        },
+                    ));
+                }
+            }
+            ';' => while chars.next_if(|&c| c != '\n').is_some() {},
+            '(' => depth += 1,
+            ')' if depth == 0 => bail!("line {line}: unbalanced parenthesis"),
+            ')' => depth -= 1,
+            c if c.is_whitespace() => {}
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            text.push('\\');
+                            text.extend(chars.next());
+                        }
+                        Some(c) => {
+                            line += usize::from(c == '\n');
+                            text.push(c);
+                        }
+                        None => bail!("line {line}: unterminated string"),
+                    }
+                }
+                entry.tokens.push(Token { text, quoted: true });
+            }
+            c => {
+                let mut text = String::from(c);
+                if c == '\\' {
+                    text.extend(chars.next());
+                }
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && !"();\"".contains(c)) {
+                    text.push(c);
+                    if c == '\\' {
+                        text.extend(chars.next());
+                    }
+                }
+                entry.tokens.push(Token {
+                    text,
+                    quoted: false,
+                });
+            }
+        }
+    }
+    if depth != 0 {
+        bail!("line {line}: unbalanced parenthesis");
+    }
+    if !entry.tokens.is_empty() {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+struct Parser {
+    origin: Name,
+    default_ttl: Option<u32>,
+    last_owner: Option<Name>,
+    last_ttl: Option<u32>,
+}
+
+impl Parser {
+    // Apply a directive, or parse a record.
+    fn entry(&mut self, entry: Entry) -> Result<Option<Record>, Error> {
+        let mut tokens = entry.tokens.iter();
+        let first = &entry.tokens[0].text;
+        if !entry.indented && first.starts_with('$') {
+            next(&mut tokens, "directive")?;
+            match first.to_ascii_uppercase().as_str() {
+                "$ORIGIN" => self.origin = self.name(next(&mut tokens, "origin")?)?,
+                "$TTL" => self.default_ttl = Some(parse_ttl(next(&mut tokens, "TTL")?)?),
+                _ => bail!("unsupported directive {first}"),
+            }
+            return match tokens.next() {
+                Some(token) => Err(anyhow!("unexpected {:?} after {first}", token.text)),
+                None => Ok(None),
+            };
+        }
+
+        let owner = match entry.indented {
+            true => self
+                .last_owner
+                .clone()
+                .ok_or(anyhow!("no previous owner"))?,
+            false => self.name(next(&mut tokens, "owner")?)?,
+        };
+        // The TTL and class may come in either order, and both are optional.
+        let mut ttl = None;
+        let r#type = loop {
+            let token = next(&mut tokens, "record type")?;
+            if ttl.is_none() && token.starts_with(|c: char| c.is_ascii_digit()) {
+                ttl = Some(parse_ttl(token)?);
+            } else if token.eq_ignore_ascii_case("IN") {
+                continue;
+            } else if ["CH", "HS", "CS"]
+                .iter()
+                .any(|c| token.eq_ignore_ascii_case(c))
+            {
+                bail!("unsupported class {token}");
+            } else {
+                break token
+                    .to_ascii_uppercase()
+                    .parse::<RecordType>()
+                    .map_err(|_| anyhow!("unknown record type {token}"))?;
+            }
+        };
+        let ttl = ttl
+            .or(self.default_ttl)
+            .or(self.last_ttl)
+            .ok_or(anyhow!("no TTL for {owner} and no $TTL directive"))?;
+
+        let rdata = match r#type {
+            RecordType::A => RData::A(next(&mut tokens, "address")?.parse()?),
+            RecordType::AAAA => RData::AAAA(next(&mut tokens, "address")?.parse()?),
+            RecordType::NS => RData::NS(self.name(next(&mut tokens, "nameserver")?)?),
+            RecordType::CNAME => RData::CNAME(self.name(next(&mut tokens, "target")?)?),
+            RecordType::MX => RData::MX {
+                preference: next(&mut tokens, "preference")?.parse()?,
+                exchange: self.name(next(&mut tokens, "exchange")?)?,
+            },
+            RecordType::SOA => RData::SOA {
+                mname: self.name(next(&mut tokens, "primary nameserver")?)?,
+                rname: self.name(next(&mut tokens, "mailbox")?)?,
+                serial: next(&mut tokens, "serial")?.parse()?,
+                refresh: parse_ttl(next(&mut tokens, "refresh")?)?,
+                retry: parse_ttl(next(&mut tokens, "retry")?)?,
+                expire: parse_ttl(next(&mut tokens, "expire")?)?,
+                minimum: parse_ttl(next(&mut tokens, "minimum")?)?,
+            },
+            RecordType::TXT => {
+                let strings = tokens
+                    .by_ref()
+                    .map(|t| unescape(&t.text))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if strings.is_empty() {
+                    bail!("missing text");
+                }
+                RData::TXT(strings)
+            }
+            r#type => bail!("unsupported record type {type}"),
+        };
+        if let Some(token) = tokens.next() {
+            let quote = if token.quoted { "\"" } else { "" };
+            bail!(
+                "unexpected {quote}{}{quote} after {type} record",
+                token.text
+            );
+        }
+
+        self.last_owner = Some(owner.clone());
+        self.last_ttl = Some(ttl);
+        Ok(Some(Record {
+            name: owner,
+            r#type,
+            class: 1,
+            ttl,
+            rdata,
+        }))
+    }
+
+    fn name(&self, s: &str) -> Result<Name, Error> {
+        parse_name(s, &self.origin)
+    }
+}
+
+fn next<'a>(tokens: &mut impl Iterator<Item = &'a Token>, what: &str) -> Result<&'a str, Error> {
+    tokens
+        .next()
+        .map(|t| t.text.as_str())
+        .ok_or(anyhow!("missing {what}"))
+}
+
+// Parse a TTL in seconds, or as a sequence of numbers with units like BIND accepts: 1h30m is
+// 5400 seconds. The units are s, m, h, d and w.
+fn parse_ttl(s: &str) -> Result<u32, Error> {
+    if let Ok(ttl) = s.parse() {
+        return Ok(ttl);
+    }
+    let invalid = || anyhow!("invalid TTL {s}");
+    let mut total: u32 = 0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let n: u32 = rest[..digits].parse().map_err(|_| invalid())?;
+        let unit = match rest.as_bytes()[digits].to_ascii_lowercase() {
+            b's' => 1,
+            b'm' => 60,
+            b'h' => 3600,
+            b'd' => 86400,
+            b'w' => 604800,
+            _ => return Err(invalid()),
+        };
+        total = n
+            .checked_mul(unit)
+            .and_then(|n| total.checked_add(n))
+            .ok_or_else(invalid)?;
+        rest = &rest[digits + 1..];
+    }
+    Ok(total)
+}
+
+// Turn a character string from the file into bytes: \X stands for X and \DDD for the byte with
+// that decimal value.
+fn unescape(s: &str) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(d) if d.is_ascii_digit() => {
+                    let digits: String = [Some(d), chars.next(), chars.next()]
+                        .into_iter()
+                        .collect::<Option<_>>()
+                        .ok_or(anyhow!("incomplete escape in {s:?}"))?;
+                    bytes.push(
+                        digits
+                            .parse()
+                            .map_err(|_| anyhow!("invalid escape \\{digits} in {s:?}"))?,
+                    );
+                }
+                Some(c) => bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                None => bail!("{s:?} ends with a backslash"),
+            },
+            c => bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    if bytes.len() > 255 {
+        bail!("character string {s:?} is longer than 255 bytes");
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    const ZONE: &str = r#"
+$ORIGIN example.com.
+$TTL 1h
+@       IN  SOA  ns1 hostmaster.example.com. (
+                 2024010101 ; serial
+                 1d 2h 4w 1h )
+        IN  NS   ns1
+        IN  MX   10 mail
+ns1         A    192.0.2.1
+www     300 A    192.0.2.2
+            TXT  "v=spf1 -all" "quote \" and \059"
+mail    IN  60 AAAA 2001:db8::25
+$ORIGIN sub.example.com.
+alias       CNAME www.example.com.
+"#;
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    fn rdata(zone: &Zone, owner: &str) -> Vec<String> {
+        zone.records(&name(owner))
+            .iter()
+            .map(|r| format!("{} {} {}", r.ttl, r.r#type, r.rdata))
+            .collect()
+    }
+
+    #[test]
+    fn parse_zone_file() {
+        let zone = Zone::parse(ZONE, Name::root()).unwrap();
+        assert_eq!(zone.origin, name("example.com"));
+        assert_eq!(zone.len(), 8);
+        assert_eq!(
+            rdata(&zone, "example.com"),
+            [
+                "3600 SOA ns1.example.com. hostmaster.example.com. 2024010101 86400 7200 2419200 3600",
+                "3600 NS ns1.example.com.",
+                "3600 MX 10 mail.example.com.",
+            ]
+        );
+        assert_eq!(
+            rdata(&zone, "www.example.com"),
+            [
+                "300 A 192.0.2.2",
+                "3600 TXT \"v=spf1 -all\" \"quote \\\" and ;\""
+            ]
+        );
+        assert_eq!(rdata(&zone, "mail.example.com"), ["60 AAAA 2001:db8::25"]);
+        assert_eq!(
+            rdata(&zone, "alias.sub.example.com"),
+            ["3600 CNAME www.example.com."]
+        );
+        // sub.example.com has no records of its own but has a name below it.
+        assert!(zone.contains(&name("sub.example.com")));
+        assert!(!zone.contains(&name("nope.example.com")));
+    }
+
+    #[test]
+    fn reject_invalid_zones() {
+        let soa = "@ 60 SOA ns hostmaster 1 2 3 4 5\n";
+        let parse = |records: &str| Zone::parse(&format!("{soa}{records}"), name("example"));
+        assert!(parse("").is_ok());
+        let err = parse("www SRV 0 0 80 www").err().unwrap();
+        assert_eq!(format!("{err:#}"), "line 2: unsupported record type SRV");
+        assert!(parse("www.other. A 192.0.2.1").is_err());
+        assert!(parse("www A 192.0.2.1 extra").is_err());
+        assert!(parse("www A (192.0.2.1").is_err());
+        assert!(parse("www CH A 192.0.2.1").is_err());
+        assert!(parse("$INCLUDE other.zone").is_err());
+        assert!(parse("@ SOA ns hostmaster 1 2 3 4 5").is_err());
+        assert!(Zone::parse("www 60 A 192.0.2.1", name("example")).is_err());
+        // Without $TTL the first record must have a TTL.
+        assert!(Zone::parse("@ SOA ns hostmaster 1 2 3 4 5", name("example")).is_err());
+    }
+
+    #[test]
+    fn parse_ttls() {
+        assert_eq!(parse_ttl("300").unwrap(), 300);
+        assert_eq!(parse_ttl("1h30m").unwrap(), 5400);
+        assert_eq!(parse_ttl("1W").unwrap(), 604800);
+        assert!(parse_ttl("1x").is_err());
+        assert!(parse_ttl("h").is_err());
+        assert!(parse_ttl("10").is_ok() && parse_ttl("1h1").is_err());
+    }
+
+    // Damaged zone files are rejected with an error, never a panic.
+    #[quickcheck]
+    fn parse_damaged_zones(damage: Vec<(usize, char)>, length: usize) -> bool {
+        let mut chars: Vec<char> = ZONE.chars().collect();
+        for (i, c) in damage {
+            let i = i % chars.len();
+            chars[i] = c;
+        }
+        chars.truncate(length % (chars.len() + 1));
+        let text: String = chars.into_iter().collect();
+        if let Ok(zone) = Zone::parse(&text, Name::root()) {
+            zone.soa().to_string();
+        }
+        true
+    }
+}